@@ -1,10 +1,101 @@
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use std::fs;
 
 #[derive(Deserialize)]
 pub struct HalDefinition {
+    /// Version of the configuration format, not checked yet
+    #[allow(dead_code)]
     pub version: String,
     pub svd_patch_path: String,
+    #[serde(default)]
+    pub peripheral_access: PeripheralAccess,
+    /// Register access needs the ownership token of the peripheral, see `Peripherals::take`
+    #[serde(default)]
+    pub peripheral_tokens: bool,
+    #[serde(default)]
+    pub entities: Vec<HalEntityDefinition>,
+    #[serde(default)]
+    pub atomic: Vec<HalAtomicDefinition>,
+    /// Artifacts written in addition to the Rust crate
+    #[serde(default)]
+    pub outputs: Vec<ExtraOutput>,
+}
+
+/// How the generated code finds the register blocks of the peripherals
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeripheralAccess {
+    /// Each register block is an extern static that `peripheral.x` places at its base address,
+    /// the peripherals can be relocated at link time
+    #[default]
+    Symbols,
+    /// Each register block is a const raw pointer to its base address, no linker script needed
+    Pointers,
+}
+
+/// An artifact generated from the same patched svd as the Rust crate, written into the crate
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtraOutput {
+    /// Markdown register reference with bit-field diagrams, doc/registers.md
+    Reference,
+    /// C header with base addresses, register structs and field masks, include/<device>.h
+    CHeader,
+    /// JSON model of the resolved registers and entities for tooling, model.json
+    Model,
+}
+
+/// How `write` and `modify` of the registers of a peripheral (or of one register if `register`
+/// is given) keep interrupts from clobbering a read-modify-write
+#[derive(Deserialize)]
+pub struct HalAtomicDefinition {
+    pub peripheral: String,
+    pub register: Option<String>,
+    pub policy: AtomicPolicy,
+    /// Register whose one bits set the bits of the register (set_clear)
+    pub set: Option<String>,
+    /// Register whose one bits clear the bits of the register (set_clear)
+    pub clear: Option<String>,
+    /// Position of the clear bits in `clear`, e.g. 16 for the upper half of a GPIO BSRR
+    #[serde(default)]
+    pub clear_shift: u32,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AtomicPolicy {
+    /// Runs the read-modify-write in a section of the critical-section crate
+    CriticalSection,
+    /// Writes each bit through the Cortex-M3/M4 bit-band alias of the register
+    BitBand,
+    /// Writes the bits through a pair of set and clear registers, the register is not read
+    SetClear,
+}
+
+/// A named group of fields that belong together from the user's point of view
+/// (e.g. "system_clock"), even if the SVD scatters them over several registers
+/// or peripherals.
+#[derive(Deserialize)]
+pub struct HalEntityDefinition {
+    pub name: String,
+    pub fields: Vec<HalFieldDefinition>,
+}
+
+#[derive(Deserialize)]
+pub struct HalFieldDefinition {
+    /// Friendly name used for the entity accessors
+    pub name: String,
+    pub peripheral: String,
+    pub register: String,
+    /// Name of the field inside the SVD register
+    pub field: String,
+    /// Invisible fields are part of the entity registers but get no accessors
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+}
+
+fn default_visible() -> bool {
+    true
 }
 
 impl HalDefinition {
@@ -2,6 +2,7 @@ use super::hal_definition::HalDefinition;
 
 pub struct Input {
     pub svd: patch_svd::Svd,
+    pub hal_definition: HalDefinition,
 }
 
 impl Input {
@@ -15,6 +16,7 @@ impl Input {
         //Take svd_path from hal_configuration
         Input {
             svd: patch_svd::read_svd_config(&svd_patch_path),
+            hal_definition,
         }
     }
 }
@@ -1,4 +1,6 @@
 mod hal_definition;
+#[allow(clippy::module_inception)]
 mod input;
 
+pub use hal_definition::{AtomicPolicy, ExtraOutput, HalAtomicDefinition, HalEntityDefinition, HalFieldDefinition};
 pub use input::Input;
@@ -9,7 +9,7 @@ fn main() {
     if args.len() != 3 {
         println!(
             "usage: {} <hal_config.yaml> <output-dir>",
-            args.get(0).unwrap()
+            args.first().unwrap()
         );
         std::process::exit(1);
     }
@@ -28,5 +28,13 @@ fn main() {
         println!("output path must end with a /");
         std::process::exit(1);
     }
-    output.write(output_dir);
+    output.write(output_dir.clone());
+    for extra_output in &input.hal_definition.outputs {
+        match extra_output {
+            input::ExtraOutput::Reference => output.write_reference(output_dir.clone()),
+            input::ExtraOutput::CHeader => output.write_c_header(output_dir.clone()),
+            input::ExtraOutput::Model => output.write_model(output_dir.clone()),
+        }
+    }
+    println!("{}", output.summary());
 }
@@ -0,0 +1,220 @@
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Expands `dim` registers and clusters. Every register gets the list of its `elements`
+/// (struct field name and absolute offset) which is used for the register block layout.
+/// Arrays keep a single entry in `registers` with `dim`/`dimIncrement`, so that all elements
+/// share one register module. Registers of a cluster are moved into `registers` with the
+/// cluster name as prefix.
+pub fn annotate(device: &mut Value) {
+    for content in walk::peripherals_mut(device) {
+        let peripheral_name = content["name"].as_str().unwrap_or_default().to_string();
+        let mut registers = vec![];
+        for register in walk::registers(content) {
+            registers.push(expand_register(register, "", 0, None));
+        }
+        for cluster in content["clusters"].as_array().into_iter().flatten() {
+            let cluster_name = cluster["name"].as_str().unwrap();
+            if cluster["clusters"].as_array().is_some_and(|c| !c.is_empty()) {
+                panic!(
+                    "Nested clusters are not supported ({}.{})",
+                    peripheral_name, cluster_name
+                );
+            }
+            let offset = cluster["addressOffset"].as_u64().unwrap();
+            let indices = dim_indices(cluster);
+            for register in cluster["registers"].as_array().into_iter().flatten() {
+                if register["dim"].is_u64() && indices.is_some() {
+                    panic!(
+                        "Register arrays inside of cluster arrays are not supported ({}.{}.{})",
+                        peripheral_name, cluster_name, register["name"]
+                    );
+                }
+                let expanded = match &indices {
+                    Some(indices) => {
+                        let elements: Vec<String> = indices
+                            .iter()
+                            .map(|index| element_name(cluster_name, index))
+                            .collect();
+                        expand_register(
+                            register,
+                            cluster_name,
+                            offset,
+                            Some((elements, cluster["dimIncrement"].as_u64().unwrap())),
+                        )
+                    }
+                    None => expand_register(register, cluster_name, offset, None),
+                };
+                registers.push(expanded);
+            }
+        }
+        content["registers"] = Value::Array(registers);
+    }
+}
+
+/// `cluster_dim` carries the element names and the increment of a surrounding cluster array
+fn expand_register(
+    register: &Value,
+    cluster_name: &str,
+    cluster_offset: u64,
+    cluster_dim: Option<(Vec<String>, u64)>,
+) -> Value {
+    let name = register["name"].as_str().unwrap();
+    let offset = cluster_offset + register["addressOffset"].as_u64().unwrap();
+    let with_cluster = |name: &str| {
+        if cluster_name.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", base_name(cluster_name), name)
+        }
+    };
+
+    let mut expanded = register.clone();
+    let (module_name, elements, increment) = match (dim_indices(register), cluster_dim) {
+        (Some(indices), _) => (
+            with_cluster(&base_name(name)),
+            indices
+                .iter()
+                .map(|index| with_cluster(&element_name(name, index)))
+                .collect(),
+            register["dimIncrement"].as_u64().unwrap(),
+        ),
+        (None, Some((cluster_elements, cluster_increment))) => (
+            with_cluster(name),
+            cluster_elements
+                .iter()
+                .map(|element| format!("{}_{}", element, name))
+                .collect(),
+            cluster_increment,
+        ),
+        (None, None) => (with_cluster(name), vec![with_cluster(name)], 0),
+    };
+    expanded["name"] = json!(module_name);
+    // Registers of a cluster are moved into the peripheral, so their offset is relative to it
+    expanded["addressOffset"] = json!(offset);
+    expanded["elements"] = Value::Array(
+        elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| {
+                json!({
+                    "name": element,
+                    "addressOffset": offset + index as u64 * increment,
+                })
+            })
+            .collect(),
+    );
+    if increment != 0 {
+        expanded["dim"] = json!(elements.len());
+        expanded["dimIncrement"] = json!(increment);
+    }
+    expanded
+}
+
+/// Indices of a `dim` element, `None` if it is no array
+fn dim_indices(element: &Value) -> Option<Vec<String>> {
+    let dim = element["dim"].as_u64()?;
+    let indices: Vec<String> = match element["dimIndex"].as_str() {
+        Some(range) if range.contains('-') => {
+            let mut bounds = range.split('-').map(|bound| bound.trim().parse::<u64>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(first)), Some(Ok(last))) => (first..=last).map(|i| i.to_string()).collect(),
+                _ => panic!("Unsupported dimIndex {}", range),
+            }
+        }
+        Some(list) => list.split(',').map(|index| index.trim().to_string()).collect(),
+        None => (0..dim).map(|i| i.to_string()).collect(),
+    };
+    if indices.len() as u64 != dim {
+        panic!(
+            "dimIndex of {} does not contain {} elements",
+            element["name"], dim
+        );
+    }
+    Some(indices)
+}
+
+fn element_name(name: &str, index: &str) -> String {
+    if name.contains("[%s]") {
+        name.replace("[%s]", index)
+    } else if name.contains("%s") {
+        name.replace("%s", index)
+    } else {
+        format!("{}{}", name, index)
+    }
+}
+
+/// Array name without placeholder, e.g. `CCR%s` -> `CCR`, `CH%s_` -> `CH`
+fn base_name(name: &str) -> String {
+    name.replace("[%s]", "")
+        .replace("%s", "")
+        .trim_matches('_')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(dim: u64, dim_index: Option<&str>) -> Option<Vec<String>> {
+        dim_indices(&json!({"name": "CCR%s", "dim": dim, "dimIndex": dim_index}))
+    }
+
+    #[test]
+    fn dim_index_defaults_to_numbers() {
+        assert_eq!(indices(3, None).unwrap(), vec!["0", "1", "2"]);
+        assert_eq!(dim_indices(&json!({"name": "CR"})), None);
+    }
+
+    #[test]
+    fn dim_index_range() {
+        assert_eq!(indices(3, Some("1-3")).unwrap(), vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn dim_index_list() {
+        assert_eq!(indices(3, Some("A, B,C")).unwrap(), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not contain 4 elements")]
+    fn dim_index_must_match_dim() {
+        indices(4, Some("1-3"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported dimIndex A-C")]
+    fn dim_index_range_must_be_numeric() {
+        indices(3, Some("A-C"));
+    }
+
+    #[test]
+    fn element_names() {
+        assert_eq!(element_name("CCR%s", "2"), "CCR2");
+        assert_eq!(element_name("CH[%s]", "2"), "CH2");
+        assert_eq!(base_name("CH%s_"), "CH");
+    }
+
+    #[test]
+    fn cluster_registers_are_relative_to_the_peripheral() {
+        let mut device = json!({"peripherals": [{"content": {
+            "name": "DMA1",
+            "registers": [{"name": "ISR", "addressOffset": 0}],
+            "clusters": [{
+                "name": "CH%s",
+                "addressOffset": 0xC,
+                "dim": 2,
+                "dimIncrement": 0x14,
+                "dimIndex": "A,B",
+                "registers": [{"name": "PAR", "addressOffset": 0x4}],
+            }],
+        }}]});
+        annotate(&mut device);
+        let par = &device["peripherals"][0]["content"]["registers"][1];
+        assert_eq!(par["name"], "CH_PAR");
+        assert_eq!(par["addressOffset"], 0x10);
+        assert_eq!(par["elements"][0]["addressOffset"], 0x10);
+        assert_eq!(par["elements"][1]["addressOffset"], 0x24);
+    }
+}
@@ -0,0 +1,200 @@
+use super::super::input::{AtomicPolicy, HalAtomicDefinition};
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Bit-band regions of Cortex-M3/M4, SRAM and peripherals
+const BIT_BAND_REGIONS: [(u64, u64); 2] = [(0x2000_0000, 0x2010_0000), (0x4000_0000, 0x4010_0000)];
+
+/// Stores the policy of the registers listed in the `atomic` section of the hal configuration as
+/// `atomic` ({policy, setOffset, clearOffset, clearShift}, offsets relative to the register).
+/// `criticalSection` of the device tells whether the generated crate needs the critical-section crate.
+pub fn annotate(device: &mut Value, definitions: &[HalAtomicDefinition]) {
+    let cpu = device["cpu"]["name"].as_str().map(str::to_uppercase);
+    let mut critical_section = false;
+    for definition in definitions {
+        // Derived peripherals without overrides share the Writer, but write their own addresses
+        let derivatives: Vec<Value> = walk::peripherals(device)
+            .filter(|p| p["derivedFrom"] == definition.peripheral.as_str() && p["overrides"] == false)
+            .cloned()
+            .collect();
+        // Derived peripherals with overrides have their own copy of the registers, which inherits
+        // the policy unless the peripheral is configured itself
+        let copies: Vec<Value> = walk::peripherals(device)
+            .filter(|p| p["derivedFrom"] == definition.peripheral.as_str() && p["overrides"] == true)
+            .filter(|p| !definitions.iter().any(|d| p["name"] == d.peripheral.as_str()))
+            .map(|p| p["name"].clone())
+            .collect();
+        let peripheral = walk::peripherals_mut(device)
+            .find(|p| p["name"] == definition.peripheral.as_str())
+            .unwrap_or_else(|| panic!("Atomic policy: peripheral {} not found in svd", definition.peripheral));
+        if peripheral["derivedFrom"].is_string() && peripheral["overrides"] == false {
+            panic!(
+                "Atomic policy: {} shares the registers of {}, configure the policy there",
+                definition.peripheral, peripheral["derivedFrom"]
+            );
+        }
+        critical_section |= apply(peripheral, definition, &derivatives, cpu.as_deref());
+        for name in copies {
+            let copy = walk::peripherals_mut(device).find(|p| p["name"] == name).unwrap();
+            critical_section |= apply(copy, definition, &[], cpu.as_deref());
+        }
+    }
+    device["criticalSection"] = json!(critical_section);
+}
+
+/// Stores the policy of `definition` in the registers of `peripheral`, returns whether it needs a
+/// critical section
+fn apply(peripheral: &mut Value, definition: &HalAtomicDefinition, derivatives: &[Value], cpu: Option<&str>) -> bool {
+    let peripheral_name = peripheral["name"].as_str().unwrap().to_string();
+    let registers = peripheral["registers"].as_array_mut().unwrap();
+    let offset_of = |registers: &Vec<Value>, name: &str| {
+        registers
+            .iter()
+            .find(|r| r["name"] == name)
+            .and_then(|r| r["addressOffset"].as_i64())
+            .unwrap_or_else(|| panic!("Atomic policy: register {}.{} not found in svd", peripheral_name, name))
+    };
+    let (set_offset, clear_offset) = match definition.policy {
+        AtomicPolicy::SetClear => {
+            let set = definition.set.as_ref().expect("Atomic policy set_clear needs a set register");
+            let clear = definition.clear.as_ref().expect("Atomic policy set_clear needs a clear register");
+            (offset_of(registers, set), offset_of(registers, clear))
+        }
+        _ => (0, 0),
+    };
+    if let Some(register) = &definition.register {
+        offset_of(registers, register);
+    }
+    let mut critical_section = false;
+    for register in registers
+        .iter_mut()
+        .filter(|r| definition.register.as_ref().is_none_or(|name| r["name"] == name.as_str()))
+    {
+        let name = format!("{}.{}", peripheral_name, register["name"].as_str().unwrap());
+        match definition.policy {
+            AtomicPolicy::CriticalSection => critical_section = true,
+            AtomicPolicy::BitBand => {
+                check_bit_band(&name, register, cpu);
+                for derivative in derivatives {
+                    let derived_register = walk::registers(derivative)
+                        .find(|r| r["name"] == register["name"])
+                        .unwrap();
+                    let derived_name = format!(
+                        "{}.{}",
+                        derivative["name"].as_str().unwrap(),
+                        register["name"].as_str().unwrap()
+                    );
+                    check_bit_band(&derived_name, derived_register, cpu);
+                }
+            }
+            AtomicPolicy::SetClear => {
+                if register["dim"].is_number() {
+                    panic!("Atomic policy: set_clear is not supported for the register array {}", name);
+                }
+                if u64::from(definition.clear_shift) >= register["size"].as_u64().unwrap() {
+                    panic!("Atomic policy: clear_shift of {} is not less than the register size", name);
+                }
+            }
+        }
+        let offset = register["addressOffset"].as_i64().unwrap();
+        register["atomic"] = json!({
+            "policy": definition.policy,
+            "setOffset": set_offset - offset,
+            "clearOffset": clear_offset - offset,
+            "clearShift": definition.clear_shift,
+        });
+    }
+    critical_section
+}
+
+/// The hardware performs a read-modify-write of the whole register for each bit-band write, so the
+/// register must not have bits that change when they are written back or read.
+fn check_bit_band(name: &str, register: &Value, cpu: Option<&str>) {
+    if let Some(cpu) = cpu.filter(|cpu| !["CM3", "CM4", "SC300"].contains(cpu)) {
+        panic!("Atomic policy: the {} core of {} has no bit-band alias", cpu, name);
+    }
+    for element in register["elements"].as_array().into_iter().flatten() {
+        let address = element["address"].as_u64().unwrap();
+        if !BIT_BAND_REGIONS.iter().any(|(start, end)| (*start..*end).contains(&address)) {
+            panic!("Atomic policy: {} at {:#X} is outside of the bit-band regions", name, address);
+        }
+    }
+    if register["writeZerosMask"].as_u64().unwrap_or(0) != 0
+        || register["writeOnesMask"].as_u64().unwrap_or(0) != 0
+        || register["readAction"].is_string()
+    {
+        panic!("Atomic policy: bit-band writes of {} would clear its flags", name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(address: u64) -> Value {
+        json!({"name": "DR", "size": 32, "addressOffset": 0, "elements": [{"name": "DR", "address": address}]})
+    }
+
+    fn bit_band(peripheral: &str) -> HalAtomicDefinition {
+        HalAtomicDefinition {
+            peripheral: peripheral.to_string(),
+            register: None,
+            policy: AtomicPolicy::BitBand,
+            set: None,
+            clear: None,
+            clear_shift: 0,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Atomic policy: UART2.DR at 0x50000000 is outside of the bit-band regions")]
+    fn bit_band_checks_derived_peripherals() {
+        let mut device = json!({"cpu": {"name": "CM3"}, "peripherals": [
+            {"content": {"name": "UART1", "registers": [register(0x4000_0000)]}},
+            {"derived": {
+                "name": "UART2",
+                "derivedFrom": "UART1",
+                "overrides": false,
+                "registers": [register(0x5000_0000)],
+            }},
+        ]});
+        annotate(&mut device, &[bit_band("UART1")]);
+    }
+
+    #[test]
+    fn derived_peripherals_with_overrides_inherit_the_policy() {
+        let mut device = json!({"cpu": {"name": "CM3"}, "peripherals": [
+            {"content": {"name": "UART1", "registers": [register(0x4000_0000)]}},
+            {"derived": {
+                "name": "UART2",
+                "derivedFrom": "UART1",
+                "overrides": true,
+                "registers": [register(0x4000_0400)],
+            }},
+            {"derived": {
+                "name": "UART3",
+                "derivedFrom": "UART1",
+                "overrides": true,
+                "registers": [register(0x4000_0800)],
+            }},
+        ]});
+        let critical_section = HalAtomicDefinition {
+            policy: AtomicPolicy::CriticalSection,
+            ..bit_band("UART3")
+        };
+        annotate(&mut device, &[bit_band("UART1"), critical_section]);
+        let policy = |peripheral: usize| device["peripherals"][peripheral]
+            .as_object()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()["registers"][0]["atomic"]["policy"]
+            .clone();
+        assert_eq!(policy(0), "bit_band");
+        assert_eq!(policy(1), "bit_band");
+        // A peripheral configured itself keeps its own policy
+        assert_eq!(policy(2), "critical_section");
+        assert_eq!(device["criticalSection"], true);
+    }
+}
@@ -0,0 +1,93 @@
+use super::walk::{self, FIELD_KINDS};
+
+use serde_json::{json, Value};
+
+/// Narrows raw integer fields to the smallest type that holds their bit width and stores the
+/// values a setter accepts as `range` ([minimum, maximum]) from the bit width and the
+/// `writeConstraint` range. Setters of fields with enumerated values only get the width check.
+pub fn annotate(device: &mut Value) {
+    for peripheral in walk::peripherals_mut(device) {
+        for register in walk::registers_mut(peripheral) {
+            for field in walk::fields_mut(register, &FIELD_KINDS) {
+                let mask = field["mask"].as_u64().unwrap_or(0);
+                let width = field["bitWidth"].as_u64().unwrap_or_else(|| mask.count_ones() as u64);
+                let is_integer = field["fieldType"]["raw"]
+                    .as_str()
+                    .is_some_and(|raw| raw != "bool");
+                if is_integer {
+                    field["fieldType"]["raw"] = json!(narrowest_type(width));
+                }
+                if field["fieldType"]["raw"] == "bool" {
+                    continue;
+                }
+                let width_maximum = mask >> mask.trailing_zeros();
+                let range = &field["writeConstraint"]["range"];
+                let minimum = range["minimum"].as_u64().unwrap_or(0);
+                let maximum = range["maximum"].as_u64().unwrap_or(width_maximum).min(width_maximum);
+                field["range"] = json!([minimum, maximum]);
+            }
+        }
+    }
+}
+
+fn narrowest_type(width: u64) -> &'static str {
+    match width {
+        0..=8 => "u8",
+        9..=16 => "u16",
+        17..=32 => "u32",
+        _ => "u64",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Field type and range of a field after annotate
+    fn constrain(field: Value) -> (Value, Value) {
+        let mut device = json!({"peripherals": [
+            {"content": {"name": "TIM1", "registers": [{"name": "CR", "writeFields": [field]}]}}
+        ]});
+        annotate(&mut device);
+        let field = &device["peripherals"][0]["content"]["registers"][0]["writeFields"][0];
+        (field["fieldType"].clone(), field["range"].clone())
+    }
+
+    #[test]
+    fn narrowest_integer_of_the_width() {
+        assert_eq!(narrowest_type(1), "u8");
+        assert_eq!(narrowest_type(8), "u8");
+        assert_eq!(narrowest_type(9), "u16");
+        assert_eq!(narrowest_type(17), "u32");
+        assert_eq!(narrowest_type(33), "u64");
+    }
+
+    #[test]
+    fn range_of_the_width() {
+        let field = json!({"name": "PSC", "mask": 0xFFF0, "bitWidth": 12, "fieldType": {"raw": "u32"}});
+        assert_eq!(constrain(field), (json!({"raw": "u16"}), json!([0, 0xFFF])));
+    }
+
+    #[test]
+    fn range_of_the_write_constraint() {
+        let field = json!({
+            "name": "DIV",
+            "mask": 0xF0,
+            "fieldType": {"raw": "u32"},
+            "writeConstraint": {"range": {"minimum": 2, "maximum": 20}},
+        });
+        // The maximum is limited to the width of the field
+        assert_eq!(constrain(field), (json!({"raw": "u8"}), json!([2, 0xF])));
+    }
+
+    #[test]
+    fn bool_and_enum_fields() {
+        let field = json!({"name": "EN", "mask": 0x1, "fieldType": {"raw": "bool"}});
+        assert_eq!(constrain(field), (json!({"raw": "bool"}), Value::Null));
+        let field = json!({"name": "MODE", "mask": 0x6, "fieldType": {"enum": {"typeName": "MODE_A"}}});
+        assert_eq!(
+            constrain(field),
+            (json!({"enum": {"typeName": "MODE_A"}}), json!([0, 3]))
+        );
+    }
+}
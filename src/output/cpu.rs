@@ -0,0 +1,132 @@
+use serde_json::{json, Value};
+
+/// Fills the optional elements of `cpu` with the svd defaults and stores the rust target that
+/// matches the core as `targetTriple` (null if there is none).
+pub fn annotate(device: &mut Value) {
+    let cpu = match device.get_mut("cpu") {
+        Some(cpu) if cpu.is_object() => cpu,
+        _ => return,
+    };
+    for (key, default) in [
+        ("name", json!("other")),
+        ("revision", json!("r0p0")),
+        ("mpuPresent", json!(false)),
+        ("fpuPresent", json!(false)),
+        ("vtorPresent", json!(true)),
+        ("endian", json!("little")),
+        ("nvicPrioBits", json!(default_prio_bits(cpu))),
+        ("vendorSystickConfig", json!(false)),
+    ]
+    .iter()
+    {
+        if cpu[*key].is_null() {
+            cpu[*key] = default.clone();
+        }
+    }
+    cpu["targetTriple"] = json!(target_triple(cpu));
+}
+
+/// The number of priority bits CMSIS assumes if the device header does not define it
+fn default_prio_bits(cpu: &Value) -> u64 {
+    match cpu["name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_uppercase()
+        .as_str()
+    {
+        "CM0" | "CM0PLUS" | "CM0+" | "CM1" | "SC000" | "CM23" => 2,
+        _ => 3,
+    }
+}
+
+fn target_triple(cpu: &Value) -> Option<&'static str> {
+    let name = cpu["name"].as_str().unwrap_or_default().to_uppercase();
+    let fpu = cpu["fpuPresent"].as_bool().unwrap_or(false);
+    if cpu["endian"] == "big" {
+        return None;
+    }
+    let triple = match name.as_str() {
+        "CM0" | "CM0PLUS" | "CM0+" | "CM1" | "SC000" => "thumbv6m-none-eabi",
+        "CM3" | "SC300" => "thumbv7m-none-eabi",
+        "CM4" | "CM7" if fpu => "thumbv7em-none-eabihf",
+        "CM4" | "CM7" => "thumbv7em-none-eabi",
+        "CM23" => "thumbv8m.base-none-eabi",
+        "CM33" | "CM35P" | "CM55" | "CM85" if fpu => "thumbv8m.main-none-eabihf",
+        "CM33" | "CM35P" | "CM55" | "CM85" => "thumbv8m.main-none-eabi",
+        "CA5" | "CA7" | "CA8" | "CA9" | "CA15" | "CA17" if fpu => "armv7a-none-eabihf",
+        "CA5" | "CA7" | "CA8" | "CA9" | "CA15" | "CA17" => "armv7a-none-eabi",
+        _ => return None,
+    };
+    Some(triple)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::templates;
+    use super::*;
+
+    fn triple(name: &str, fpu: bool) -> Option<&'static str> {
+        target_triple(&json!({"name": name, "fpuPresent": fpu, "endian": "little"}))
+    }
+
+    #[test]
+    fn cores_map_to_their_target() {
+        assert_eq!(triple("CM0PLUS", false), Some("thumbv6m-none-eabi"));
+        assert_eq!(triple("CM3", false), Some("thumbv7m-none-eabi"));
+        assert_eq!(triple("CM4", false), Some("thumbv7em-none-eabi"));
+        assert_eq!(triple("cm7", true), Some("thumbv7em-none-eabihf"));
+        assert_eq!(triple("CM23", false), Some("thumbv8m.base-none-eabi"));
+        assert_eq!(triple("CM33", true), Some("thumbv8m.main-none-eabihf"));
+        assert_eq!(triple("CA7", false), Some("armv7a-none-eabi"));
+    }
+
+    #[test]
+    fn unknown_and_big_endian_cores_have_no_target() {
+        assert_eq!(triple("other", false), None);
+        assert_eq!(triple("", false), None);
+        let big_endian = json!({"name": "CM4", "fpuPresent": false, "endian": "big"});
+        assert_eq!(target_triple(&big_endian), None);
+    }
+
+    #[test]
+    fn optional_elements_get_defaults() {
+        let mut device = json!({"cpu": {"name": "CM0PLUS"}});
+        annotate(&mut device);
+        let cpu = &device["cpu"];
+        assert_eq!(cpu["revision"], "r0p0");
+        assert_eq!(cpu["endian"], "little");
+        assert_eq!(cpu["nvicPrioBits"], 2);
+        assert_eq!(cpu["mpuPresent"], false);
+        assert_eq!(cpu["vtorPresent"], true);
+        assert_eq!(cpu["targetTriple"], "thumbv6m-none-eabi");
+
+        let mut device = json!({"cpu": {"name": "CM4", "nvicPrioBits": 4}});
+        annotate(&mut device);
+        assert_eq!(device["cpu"]["nvicPrioBits"], 4);
+        assert_eq!(device["cpu"]["targetTriple"], "thumbv7em-none-eabi");
+    }
+
+    #[test]
+    fn constants_are_emitted_for_a_minimal_cpu() {
+        let mut device = json!({"cpu": {"name": "CM3"}});
+        annotate(&mut device);
+        let lib_rs = templates::render_template(templates::SRC_LIB_RS_TEMPLATE, &device);
+        for constant in [
+            "pub const CPU: &str = \"CM3\";",
+            "pub const CPU_REVISION: &str = \"r0p0\";",
+            "pub const CPU_ENDIAN: &str = \"little\";",
+            "pub const NVIC_PRIO_BITS: u8 = 3;",
+            "pub const MPU_PRESENT: bool = false;",
+            "pub const FPU_PRESENT: bool = false;",
+            "pub const VTOR_PRESENT: bool = true;",
+        ]
+        .iter()
+        {
+            assert!(
+                lib_rs.lines().any(|line| line == *constant),
+                "{} is missing",
+                constant
+            );
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use super::walk::FIELD_KINDS;
+
+use serde_json::{json, Value};
+
+/// Resolves `derivedFrom` peripherals against their base peripheral and stores the merged
+/// register list as `registers` of the derived peripheral. Registers supplied by the derived
+/// peripheral itself override the base register with the same name; their fields are merged
+/// the same way. `overrides` tells the templates whether the base register block can be reused.
+pub fn annotate(device: &mut Value) {
+    let bases: Vec<Value> = device["peripherals"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|p| &p["content"])
+        .filter(|content| content.is_object())
+        .cloned()
+        .collect();
+    let peripherals = match device["peripherals"].as_array_mut() {
+        Some(peripherals) => peripherals,
+        None => return,
+    };
+    for derived in peripherals
+        .iter_mut()
+        .filter_map(|p| p.get_mut("derived"))
+        .filter(|derived| derived.is_object())
+    {
+        let base = bases
+            .iter()
+            .find(|base| base["name"] == derived["derivedFrom"])
+            .unwrap_or_else(|| {
+                panic!(
+                    "Peripheral {} is derived from unknown peripheral {}",
+                    derived["name"], derived["derivedFrom"]
+                )
+            });
+        let overrides = derived["registers"].as_array().cloned().unwrap_or_default();
+        let mut registers = base["registers"].as_array().cloned().unwrap_or_default();
+        for register_override in &overrides {
+            match registers
+                .iter_mut()
+                .find(|register| register["name"] == register_override["name"])
+            {
+                Some(register) => merge_register(register, register_override),
+                None => registers.push(register_override.clone()),
+            }
+        }
+        if !derived["clusters"].is_array() {
+            derived["clusters"] = base["clusters"].clone();
+        }
+        derived["overrides"] = json!(!overrides.is_empty());
+        derived["registers"] = Value::Array(registers);
+    }
+}
+
+fn merge_register(register: &mut Value, register_override: &Value) {
+    for (key, value) in register_override.as_object().unwrap() {
+        if !FIELD_KINDS.contains(&key.as_str()) {
+            register[key] = value.clone();
+        }
+    }
+    for kind in FIELD_KINDS.iter() {
+        for field_override in register_override[*kind].as_array().into_iter().flatten() {
+            // The override may change the access of a field, so drop it from every list
+            for base_kind in FIELD_KINDS.iter() {
+                if let Some(fields) = register[*base_kind].as_array_mut() {
+                    fields.retain(|field| field["name"] != field_override["name"]);
+                }
+            }
+            match register[*kind].as_array_mut() {
+                Some(fields) => fields.push(field_override.clone()),
+                None => register[*kind] = json!([field_override]),
+            }
+        }
+    }
+}
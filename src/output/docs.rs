@@ -0,0 +1,174 @@
+use super::walk::{self, FIELD_KINDS};
+
+use serde_json::{json, Value};
+
+/// Prepares the rustdoc of all generated items as `doc`, a list of lines without the leading
+/// `///`. Descriptions from the svd are sanitized, followed by the address, offset, bit range,
+/// access and reset value where they apply.
+pub fn annotate(device: &mut Value) {
+    for enumerated_type in device["enumeratedTypes"].as_array_mut().into_iter().flatten() {
+        let info = format!("Enumerated values {}", enumerated_type["svdName"].as_str().unwrap());
+        enumerated_type["doc"] = json!(lines(&enumerated_type["description"], vec![info]));
+        for value in enumerated_type["values"].as_array_mut().into_iter().flatten() {
+            let info = format!("Value: {:#X}", value["value"].as_u64().unwrap());
+            value["doc"] = json!(lines(&value["description"], vec![info]));
+        }
+    }
+    for interrupt in device["interrupts"].as_array_mut().into_iter().flatten() {
+        let info = format!("Interrupt number: {}", interrupt["value"]);
+        interrupt["doc"] = json!(lines(&interrupt["description"], vec![info]));
+    }
+    for content in walk::peripherals_mut(device) {
+        let base_address = content["baseAddress"].as_u64().unwrap_or(0);
+        let mut info = format!("Base address: {:#X}", base_address);
+        if let Some(derived_from) = content["derivedFrom"].as_str() {
+            info = format!("{}, derived from {}", info, derived_from);
+        }
+        content["doc"] = json!(lines(&content["description"], vec![info]));
+        let mut items = vec![];
+        for register in walk::registers_mut(content) {
+            register["doc"] = json!(register_doc(register));
+            for element in register["elements"].as_array().into_iter().flatten() {
+                items.push((element["name"].clone(), lines(&register["description"], vec![])));
+            }
+            let register_access = register["access"].clone();
+            let register_reset = register["resetValue"].as_u64();
+            for field in walk::fields_mut(register, &FIELD_KINDS) {
+                field["doc"] = json!(field_doc(field, &register_access, register_reset));
+            }
+        }
+        for item in content["registerBlock"]["items"].as_array_mut().into_iter().flatten() {
+            if let Some((_, doc)) = items.iter().find(|(name, _)| *name == item["name"]) {
+                item["doc"] = json!(doc);
+            }
+        }
+    }
+}
+
+fn register_doc(register: &Value) -> Vec<String> {
+    let offset = register["elements"][0]["addressOffset"].as_u64().unwrap_or(0);
+    let mut info = format!("Address: {:#X}", register["address"].as_u64().unwrap_or(0));
+    if let (Some(dim), Some(increment)) = (register["dim"].as_u64(), register["dimIncrement"].as_u64()) {
+        info = format!("{} (first of {} elements every {:#X} bytes)", info, dim, increment);
+    }
+    info = format!("{}, offset: {:#X}, size: {} bits", info, offset, register["size"]);
+    if let Some(access) = register["access"].as_str() {
+        info = format!("{}, access: {}", info, access);
+    }
+    if let Some(reset_value) = register["resetValue"].as_u64() {
+        info = format!("{}, reset value: {:#X}", info, reset_value);
+    }
+    let mut info = vec![info];
+    if let Some(read_action) = register["readAction"].as_str() {
+        info.push(String::new());
+        info.push(format!(
+            "Reading it has side effects (read action: {}), so `write` does not read it.",
+            read_action
+        ));
+        info.push("The fields that are not set are written with their reset value.".to_string());
+    }
+    lines(&register["description"], info)
+}
+
+fn field_doc(field: &Value, register_access: &Value, register_reset: Option<u64>) -> Vec<String> {
+    let mask = field["mask"].as_u64().unwrap_or(0);
+    let offset = field["bitOffset"].as_u64().unwrap_or_else(|| mask.trailing_zeros() as u64);
+    let width = field["bitWidth"].as_u64().unwrap_or_else(|| mask.count_ones() as u64);
+    let mut info = if width == 1 {
+        format!("Bit {}", offset)
+    } else {
+        format!("Bits {}:{}", offset + width - 1, offset)
+    };
+    if let Some(access) = field["access"].as_str().or_else(|| register_access.as_str()) {
+        info = format!("{}, access: {}", info, access);
+    }
+    if let Some(reset_value) = register_reset {
+        info = format!("{}, reset value: {:#X}", info, (reset_value & mask) >> offset);
+    }
+    if let Some(range) = field["writeConstraint"]["range"].as_object() {
+        info = format!(
+            "{}, allowed values: {}..={}",
+            info, range["minimum"], range["maximum"]
+        );
+    }
+    if let Some(modified_write_values) = field["modifiedWriteValues"].as_str() {
+        info = format!("{}, write: {}", info, modified_write_values);
+    }
+    if let Some(read_action) = field["readAction"].as_str() {
+        info = format!("{}, read action: {}", info, read_action);
+    }
+    lines(&field["description"], vec![info])
+}
+
+/// The sanitized description, an empty line and `info`
+fn lines(description: &Value, info: Vec<String>) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(description) = description.as_str().map(sanitize).filter(|d| !d.is_empty()) {
+        lines.push(description);
+        if !info.is_empty() {
+            lines.push(String::new());
+        }
+    }
+    lines.extend(info);
+    lines
+}
+
+/// Joins the description into one line and escapes everything rustdoc would interpret
+/// as markdown or html, e.g. `[x]` as link or `<x>` as tag.
+pub fn sanitize(description: &str) -> String {
+    let mut sanitized = String::new();
+    for c in description.split_whitespace().collect::<Vec<_>>().join(" ").chars() {
+        if "\\`*_[]<>#|~".contains(c) {
+            sanitized.push('\\');
+        }
+        sanitized.push(c);
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brackets_are_no_links() {
+        assert_eq!(
+            sanitize("MSI clock ranges [3:0]"),
+            "MSI clock ranges \\[3:0\\]"
+        );
+        assert_eq!(sanitize("see [RM0351](x)"), "see \\[RM0351\\](x)");
+    }
+
+    #[test]
+    fn backticks_and_emphasis_are_literal() {
+        assert_eq!(sanitize("`CR` *must* be 0"), "\\`CR\\` \\*must\\* be 0");
+        assert_eq!(sanitize("DMA_CCR"), "DMA\\_CCR");
+    }
+
+    #[test]
+    fn tags_are_no_html() {
+        assert_eq!(sanitize("<reserved>"), "\\<reserved\\>");
+        assert_eq!(sanitize("a < b & c > d"), "a \\< b & c \\> d");
+    }
+
+    #[test]
+    fn whitespace_and_newlines_are_collapsed() {
+        assert_eq!(
+            sanitize("  Clock\n\t  control \r\n register  "),
+            "Clock control register"
+        );
+        assert_eq!(sanitize(" \n "), "");
+    }
+
+    #[test]
+    fn empty_descriptions_give_no_line() {
+        assert_eq!(
+            lines(&json!(" \n"), vec!["Bit 0".to_string()]),
+            vec!["Bit 0"]
+        );
+        assert_eq!(
+            lines(&json!("Enable"), vec!["Bit 0".to_string()]),
+            vec!["Enable", "", "Bit 0"]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use super::super::input::{HalEntityDefinition, HalFieldDefinition};
+use super::walk::{self, FIELD_KINDS};
+
+use serde_derive::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HalEntity {
+    pub name: String,
+    pub peripherals: Vec<HalPeripheral>,
+    /// Enumerated types used by the entity fields, see enums::annotate
+    pub aggregated_types: Vec<String>,
+    /// Rustdoc lines of the entity module, see docs::annotate
+    pub doc: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct HalPeripheral {
+    pub name: String,
+    pub registers: Vec<HalRegister>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HalRegister {
+    pub name: String,
+    pub size: Value,
+    /// Address of the register, or of the array element the entity refers to
+    pub address: Value,
+    /// Write masks of the whole svd register, see write_values::annotate
+    pub write_zeros_mask: Value,
+    pub write_ones_mask: Value,
+    pub read_action: Value,
+    pub reset_value: Value,
+    pub reset_mask: Value,
+    /// Read-modify-write policy, see atomic::annotate
+    pub atomic: Value,
+    pub doc: Value,
+    pub read_write_fields: Vec<HalField>,
+    pub read_fields: Vec<HalField>,
+    pub write_fields: Vec<HalField>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HalField {
+    pub name: String,
+    pub svd_name: String,
+    pub mask: Value,
+    pub field_type: Value,
+    pub range: Value,
+    pub clear: Value,
+    pub read_action: Value,
+    pub doc: Vec<String>,
+    pub visible: bool,
+}
+
+impl HalRegister {
+    fn fields_mut(&mut self, kind: &str) -> &mut Vec<HalField> {
+        match kind {
+            "readWriteFields" => &mut self.read_write_fields,
+            "readFields" => &mut self.read_fields,
+            _ => &mut self.write_fields,
+        }
+    }
+}
+
+pub fn resolve(definitions: &[HalEntityDefinition], device: &Value) -> Vec<HalEntity> {
+    definitions
+        .iter()
+        .map(|definition| {
+            let mut entity = HalEntity {
+                name: definition.name.clone(),
+                peripherals: vec![],
+                aggregated_types: vec![],
+                doc: vec![],
+            };
+            for field_definition in &definition.fields {
+                let (svd_register, svd_element) =
+                    find_register(device, &field_definition.peripheral, &field_definition.register);
+                let (kind, svd_field) = FIELD_KINDS
+                    .iter()
+                    .filter_map(|kind| {
+                        svd_register[*kind]
+                            .as_array()?
+                            .iter()
+                            .find(|field| field["name"] == field_definition.field.as_str())
+                            .map(|field| (*kind, field))
+                    })
+                    .next()
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "Entity {}: field {}.{}.{} not found in svd",
+                            definition.name,
+                            field_definition.peripheral,
+                            field_definition.register,
+                            field_definition.field
+                        )
+                    });
+
+                if let Some(type_name) = svd_field["fieldType"]["enum"]["typeName"].as_str() {
+                    if !entity.aggregated_types.iter().any(|t| t == type_name) {
+                        entity.aggregated_types.push(type_name.to_string());
+                    }
+                }
+
+                let peripheral = match entity
+                    .peripherals
+                    .iter()
+                    .position(|p| p.name == field_definition.peripheral)
+                {
+                    Some(index) => &mut entity.peripherals[index],
+                    None => {
+                        entity.peripherals.push(HalPeripheral {
+                            name: field_definition.peripheral.clone(),
+                            registers: vec![],
+                        });
+                        entity.peripherals.last_mut().unwrap()
+                    }
+                };
+                let register = match peripheral
+                    .registers
+                    .iter()
+                    .position(|r| r.name == field_definition.register)
+                {
+                    Some(index) => &mut peripheral.registers[index],
+                    None => {
+                        peripheral.registers.push(HalRegister {
+                            name: field_definition.register.clone(),
+                            size: svd_register["size"].clone(),
+                            address: svd_element["address"].clone(),
+                            write_zeros_mask: svd_register["writeZerosMask"].clone(),
+                            write_ones_mask: svd_register["writeOnesMask"].clone(),
+                            read_action: svd_register["readAction"].clone(),
+                            reset_value: svd_register["resetValue"].clone(),
+                            reset_mask: svd_register["resetMask"].clone(),
+                            atomic: svd_register["atomic"].clone(),
+                            doc: svd_register["doc"].clone(),
+                            read_write_fields: vec![],
+                            read_fields: vec![],
+                            write_fields: vec![],
+                        });
+                        peripheral.registers.last_mut().unwrap()
+                    }
+                };
+                register.fields_mut(kind).push(HalField {
+                    name: field_definition.name.clone(),
+                    svd_name: field_definition.field.clone(),
+                    mask: svd_field["mask"].clone(),
+                    field_type: svd_field["fieldType"].clone(),
+                    range: svd_field["range"].clone(),
+                    clear: svd_field["clear"].clone(),
+                    read_action: svd_field["readAction"].clone(),
+                    doc: field_doc(svd_field, field_definition),
+                    visible: field_definition.visible,
+                });
+            }
+            let registers: Vec<String> = entity
+                .peripherals
+                .iter()
+                .flat_map(|p| p.registers.iter().map(move |r| format!("{}.{}", p.name, r.name)))
+                .collect();
+            entity.doc = vec![format!("Fields of {}", registers.join(", "))];
+            entity
+        })
+        .collect()
+}
+
+fn field_doc(svd_field: &Value, definition: &HalFieldDefinition) -> Vec<String> {
+    let mut doc: Vec<String> = svd_field["doc"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|line| line.as_str().map(String::from))
+        .collect();
+    doc.push(String::new());
+    doc.push(format!(
+        "Field {}.{}.{}",
+        definition.peripheral, definition.register, definition.field
+    ));
+    doc
+}
+
+/// The svd register and the element of it that is named `register`
+fn find_register<'a>(device: &'a Value, peripheral: &str, register: &str) -> (&'a Value, &'a Value) {
+    let svd_peripheral = walk::peripherals(device)
+        .find(|p| p["name"] == peripheral)
+        .unwrap_or_else(|| panic!("Peripheral {} not found in svd", peripheral));
+    // Entities refer to single elements of register arrays, e.g. CCR2 instead of CCR
+    let registers = svd_peripheral["registers"].as_array();
+    registers
+        .and_then(|registers| {
+            registers.iter().find_map(|r| {
+                r["elements"]
+                    .as_array()?
+                    .iter()
+                    .find(|e| e["name"] == register)
+                    .map(|element| (r, element))
+            })
+        })
+        .unwrap_or_else(|| {
+            if registers.is_some_and(|registers| registers.iter().any(|r| r["name"] == register)) {
+                panic!(
+                    "Register {}.{} is an array. Please use one of its elements.",
+                    peripheral, register
+                )
+            }
+            panic!("Register {}.{} not found in svd", peripheral, register)
+        })
+}
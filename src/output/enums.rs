@@ -0,0 +1,208 @@
+use super::walk::{self, FIELD_KINDS};
+
+use serde_json::{json, Value};
+
+/// Collects the enumerated value sets of all fields into `enumeratedTypes` of the device.
+/// Sets with the same name and values are generated only once, sets that only share the name
+/// get the peripheral name as prefix. The name of the generated type is stored as `typeName`
+/// in the `fieldType` of every field; fields with derived sets get the type of their base set.
+pub fn annotate(device: &mut Value) {
+    let mut types: Vec<Value> = vec![];
+    // (peripheral, set name, type name) of every set, derived sets prefer their own peripheral
+    let mut resolved: Vec<(String, String, String)> = vec![];
+    for_each_field(device, |peripheral, field| {
+        let content = &field["fieldType"]["enum"]["content"];
+        if !content.is_object() {
+            return;
+        }
+        let svd_name = content["name"].as_str().unwrap().to_string();
+        let values = enum_values(content);
+        let existing = types
+            .iter()
+            .find(|t| t["svdName"] == svd_name.as_str() && t["values"] == values);
+        let type_name = match existing {
+            Some(existing) => existing["name"].as_str().unwrap().to_string(),
+            None => {
+                let mut type_name = svd_name.clone();
+                if types.iter().any(|t| t["name"] == type_name.as_str()) {
+                    type_name = format!("{}_{}", peripheral, svd_name);
+                }
+                if types.iter().any(|t| t["name"] == type_name.as_str()) {
+                    panic!(
+                        "Enumerated values {} of {} clash with another set of the same name",
+                        svd_name, peripheral
+                    );
+                }
+                types.push(json!({
+                    "name": type_name,
+                    "svdName": svd_name,
+                    "peripheral": peripheral,
+                    "description": content["description"],
+                    "values": values,
+                }));
+                type_name
+            }
+        };
+        field["fieldType"]["enum"]["typeName"] = json!(type_name);
+        resolved.push((peripheral.to_string(), svd_name, type_name));
+    });
+    for_each_field(device, |peripheral, field| {
+        let derived = &field["fieldType"]["enum"]["derived"];
+        if !derived.is_object() {
+            return;
+        }
+        // derivedFrom may be a path like USART1.CR1.M.M_A, the set name is the last part
+        let svd_name = derived["name"].as_str().unwrap();
+        let svd_name = svd_name.rsplit('.').next().unwrap();
+        let type_name = resolved
+            .iter()
+            .find(|(p, name, _)| p == peripheral && name == svd_name)
+            .or_else(|| resolved.iter().find(|(_, name, _)| name == svd_name))
+            .map(|(_, _, type_name)| type_name.clone())
+            .unwrap_or_else(|| panic!("Enumerated values {} not found in svd", svd_name));
+        field["fieldType"]["enum"]["typeName"] = json!(type_name);
+    });
+    device["enumeratedTypes"] = Value::Array(types);
+}
+
+fn for_each_field<F>(device: &mut Value, mut f: F)
+where
+    F: FnMut(&str, &mut Value),
+{
+    for content in walk::peripherals_mut(device) {
+        let peripheral = content["name"].as_str().unwrap_or_default().to_string();
+        for register in walk::registers_mut(content) {
+            for field in walk::fields_mut(register, &FIELD_KINDS) {
+                f(&peripheral, field);
+            }
+        }
+    }
+}
+
+/// Values with a usable Rust name. Values without a number (e.g. isDefault) and values
+/// that repeat an earlier discriminant are skipped.
+fn enum_values(content: &Value) -> Value {
+    let mut values: Vec<Value> = vec![];
+    for value in content["values"].as_array().into_iter().flatten() {
+        let discriminant = match value["value"].as_u64() {
+            Some(discriminant) => discriminant,
+            None => continue,
+        };
+        if values.iter().any(|v| v["value"] == discriminant) {
+            continue;
+        }
+        let mut name = variant_name(value["name"].as_str().unwrap_or_default());
+        if values.iter().any(|v| v["name"] == name.as_str()) {
+            name = format!("{}_{}", name, discriminant);
+        }
+        values.push(json!({
+            "name": name,
+            "value": discriminant,
+            "description": value["description"],
+        }));
+    }
+    Value::Array(values)
+}
+
+fn variant_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("_{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enum_field(name: &str, set: &str, values: &[(&str, u64)]) -> Value {
+        let values: Vec<Value> = values
+            .iter()
+            .map(|(name, value)| json!({"name": name, "value": value}))
+            .collect();
+        json!({"name": name, "fieldType": {"enum": {"content": {"name": set, "values": values}}}})
+    }
+
+    fn derived_field(name: &str, set: &str) -> Value {
+        json!({"name": name, "fieldType": {"enum": {"derived": {"name": set}}}})
+    }
+
+    fn peripheral(name: &str, fields: Vec<Value>) -> Value {
+        json!({"content": {"name": name, "registers": [{"name": "CR", "readWriteFields": fields}]}})
+    }
+
+    /// Type names of all fields in the order of the peripherals
+    fn type_names(device: &Value) -> Vec<&str> {
+        walk::peripherals(device)
+            .flat_map(walk::registers)
+            .flat_map(walk::fields)
+            .map(|field| field["fieldType"]["enum"]["typeName"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn equal_sets_share_one_type() {
+        let mut device = json!({"peripherals": [
+            peripheral("UART1", vec![enum_field("PAR", "PAR_A", &[("Even", 0), ("Odd", 1)])]),
+            peripheral("UART2", vec![enum_field("PAR", "PAR_A", &[("Even", 0), ("Odd", 1)])]),
+        ]});
+        annotate(&mut device);
+        assert_eq!(device["enumeratedTypes"].as_array().unwrap().len(), 1);
+        assert_eq!(type_names(&device), vec!["PAR_A", "PAR_A"]);
+    }
+
+    #[test]
+    fn sets_that_only_share_the_name_are_renamed() {
+        let mut device = json!({"peripherals": [
+            peripheral("UART1", vec![enum_field("PAR", "PAR_A", &[("Even", 0), ("Odd", 1)])]),
+            peripheral("UART2", vec![enum_field("PAR", "PAR_A", &[("None", 0)])]),
+        ]});
+        annotate(&mut device);
+        let types: Vec<&str> = device["enumeratedTypes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, vec!["PAR_A", "UART2_PAR_A"]);
+    }
+
+    #[test]
+    fn derived_sets_prefer_the_set_of_their_peripheral() {
+        let mut device = json!({"peripherals": [
+            peripheral("UART1", vec![enum_field("PAR", "PAR_A", &[("Even", 0), ("Odd", 1)])]),
+            peripheral("UART2", vec![
+                enum_field("PAR", "PAR_A", &[("None", 0)]),
+                derived_field("STOP", "UART2.CR.PAR.PAR_A"),
+            ]),
+            peripheral("UART3", vec![derived_field("PAR", "PAR_A")]),
+        ]});
+        annotate(&mut device);
+        assert_eq!(
+            type_names(&device),
+            vec!["PAR_A", "UART2_PAR_A", "UART2_PAR_A", "PAR_A"]
+        );
+    }
+
+    #[test]
+    fn variant_names_are_rust_identifiers() {
+        let values = enum_values(&json!({"values": [
+            {"name": "1:1", "value": 0},
+            {"name": "Div-2", "value": 1},
+            {"name": "Div_2", "value": 2},
+            {"name": "Same", "value": 2},
+            {"name": "Default", "isDefault": true},
+        ]}));
+        let names: Vec<&str> = values
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["_1_1", "Div_2", "Div_2_2"]);
+    }
+}
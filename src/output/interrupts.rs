@@ -0,0 +1,59 @@
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Collects the interrupts of all peripherals into `interrupts` of the device, sorted by their
+/// number. Peripherals that share an interrupt list it more than once, it is kept only once.
+/// `vectors` has an entry for every number up to the highest one, `null` for reserved vectors.
+/// `linkName` of the peripherals is the symbol of their register block in peripheral.x.
+pub fn annotate(device: &mut Value) {
+    let mut interrupts: Vec<Value> = vec![];
+    for peripheral in walk::peripherals(device) {
+        for interrupt in peripheral["interrupts"].as_array().into_iter().flatten() {
+            let name = interrupt["name"].as_str().unwrap();
+            let value = interrupt["value"].as_u64().unwrap();
+            match interrupts.iter().find(|i| i["value"] == value) {
+                Some(existing) if existing["name"] == name => continue,
+                Some(existing) => panic!(
+                    "Interrupts {} and {} have the same number {}",
+                    existing["name"], name, value
+                ),
+                None => {}
+            }
+            if interrupts.iter().any(|i| i["name"] == name) {
+                panic!("Interrupt {} has more than one number", name);
+            }
+            interrupts.push(json!({
+                "name": name,
+                "value": value,
+                "description": interrupt["description"],
+            }));
+        }
+    }
+    interrupts.sort_by_key(|interrupt| interrupt["value"].as_u64());
+
+    // The symbol of an interrupt handler is the name of the interrupt, peripherals of the same
+    // name get the prefix __peripheral_ so that their symbol does not clash with it
+    for peripheral in walk::peripherals_mut(device) {
+        let name = peripheral["name"].as_str().unwrap().to_string();
+        peripheral["linkName"] = if interrupts.iter().any(|i| i["name"] == name.as_str()) {
+            json!(format!("__peripheral_{}", name))
+        } else {
+            json!(name)
+        };
+    }
+
+    let count = interrupts
+        .last()
+        .map_or(0, |interrupt| interrupt["value"].as_u64().unwrap() + 1);
+    let vectors: Vec<Value> = (0..count)
+        .map(|value| {
+            interrupts
+                .iter()
+                .find(|interrupt| interrupt["value"] == value)
+                .map_or(Value::Null, |interrupt| interrupt["name"].clone())
+        })
+        .collect();
+    device["interrupts"] = Value::Array(interrupts);
+    device["vectors"] = Value::Array(vectors);
+}
@@ -0,0 +1,161 @@
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Computes the `#[repr(C)]` layout of every peripheral from the register offsets and sizes
+/// and stores it as `registerBlock` next to the registers, so that the templates only have to
+/// print it. Gaps are filled with reserved byte arrays, overlapping or misaligned registers are
+/// an error.
+pub fn annotate(device: &mut Value) {
+    let default_size = device["size"].as_u64().unwrap_or(32);
+    // Derived peripherals carry their merged registers, see derived::annotate
+    for content in walk::peripherals_mut(device) {
+        let peripheral_name = content["name"].as_str().unwrap_or_default().to_string();
+        let base_address = content["baseAddress"].as_u64().unwrap_or(0);
+        for register in walk::registers_mut(content) {
+            // Absolute addresses of the elements, the register has the one of its first element
+            for element in register["elements"].as_array_mut().into_iter().flatten() {
+                element["address"] = json!(base_address + element["addressOffset"].as_u64().unwrap());
+            }
+            register["address"] = register["elements"][0]["address"].clone();
+            if register["size"].as_u64().is_none() {
+                register["size"] = json!(default_size);
+            }
+            // The svd often inherits 32 bit reset masks for smaller registers
+            let size_mask = match register["size"].as_u64().unwrap() {
+                size if size >= 64 => u64::MAX,
+                size => (1 << size) - 1,
+            };
+            if register["resetValue"].is_u64() && !register["resetMask"].is_u64() {
+                register["resetMask"] = json!(size_mask);
+            }
+            for key in ["resetValue", "resetMask"].iter() {
+                if let Some(value) = register[*key].as_u64() {
+                    register[*key] = json!(value & size_mask);
+                }
+            }
+        }
+        let register_block = register_block(&peripheral_name, &content["registers"]);
+        content["registerBlock"] = register_block;
+    }
+}
+
+fn register_block(peripheral_name: &str, registers: &Value) -> Value {
+    // Register arrays contribute one struct field per element, see arrays::annotate
+    let mut registers: Vec<(String, u64, u64)> = registers
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|register| {
+            let size = register["size"].as_u64().unwrap();
+            if ![8, 16, 32, 64].contains(&size) {
+                panic!(
+                    "Register {}.{} has unsupported size of {} bits",
+                    peripheral_name, register["name"].as_str().unwrap_or_default(), size
+                );
+            }
+            register["elements"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(move |element| {
+                    (
+                        element["name"].as_str().unwrap().to_string(),
+                        element["addressOffset"].as_u64().unwrap(),
+                        size / 8,
+                    )
+                })
+        })
+        .collect();
+    registers.sort_by_key(|(_, offset, _)| *offset);
+
+    let mut items = vec![];
+    let mut reserved = 0;
+    let mut end = 0;
+    let mut alignment = 1;
+    let mut previous = "";
+    for (name, offset, size) in &registers {
+        // repr(C) would pad the field to its alignment and move it away from the register
+        if offset % size != 0 {
+            panic!(
+                "Register {}.{} at offset {:#X} is not aligned to its size of {} bits",
+                peripheral_name,
+                name,
+                offset,
+                size * 8
+            );
+        }
+        if *offset < end {
+            panic!(
+                "Registers {}.{} and {}.{} overlap at offset {:#X}",
+                peripheral_name, previous, peripheral_name, name, offset
+            );
+        }
+        if *offset > end {
+            items.push(json!({
+                "name": format!("_reserved{}", reserved),
+                "offset": end,
+                "size": offset - end,
+                "reserved": true,
+            }));
+            reserved += 1;
+        }
+        items.push(json!({
+            "name": name,
+            "offset": offset,
+            "size": size,
+            "reserved": false,
+        }));
+        end = offset + size;
+        alignment = alignment.max(*size);
+        previous = name;
+    }
+    json!({
+        "items": items,
+        // repr(C) rounds the struct size up to the alignment of its largest member
+        "size": end.div_ceil(alignment) * alignment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(name: &str, offset: u64, size: u64) -> Value {
+        json!({"name": name, "size": size, "elements": [{"name": name, "addressOffset": offset}]})
+    }
+
+    #[test]
+    fn gaps_are_reserved_and_size_is_aligned() {
+        let registers = json!([register("DR", 0x8, 16), register("CR", 0x0, 32)]);
+        let block = register_block("UART", &registers);
+        let items: Vec<(&str, u64, u64)> = block["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["name"].as_str().unwrap(),
+                    item["offset"].as_u64().unwrap(),
+                    item["size"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(items, vec![("CR", 0x0, 4), ("_reserved0", 0x4, 4), ("DR", 0x8, 2)]);
+        assert_eq!(block["size"], 0xC);
+    }
+
+    #[test]
+    #[should_panic(expected = "Registers UART.CR and UART.SR overlap at offset 0x2")]
+    fn overlapping_registers_panic() {
+        let registers = json!([register("CR", 0x0, 32), register("SR", 0x2, 16)]);
+        register_block("UART", &registers);
+    }
+
+    #[test]
+    #[should_panic(expected = "Register UART.DR at offset 0x2 is not aligned to its size")]
+    fn misaligned_registers_panic() {
+        let registers = json!([register("CR", 0x0, 16), register("DR", 0x2, 32)]);
+        register_block("UART", &registers);
+    }
+}
@@ -3,11 +3,11 @@ macro_rules! render_field_type_converter {
     (bool, $value:ident) => {
         $value == 1
     };
-    (u32, $value:ident) => {
-        $value
-    };
     ( enum:$field_type:ident, $value:ident) => {
-        super::super::$field_type::from($value)
+        <super::super::$field_type as core::convert::TryFrom<u32>>::try_from($value as u32)
+    };
+    ($raw_type:ident, $value:ident) => {
+        $value as $raw_type
     };
 }
 
@@ -21,28 +21,102 @@ macro_rules! render_field_type {
     };
 }
 
+/// Enumerated fields are read fallibly, the raw value is returned if it has no variant
+#[macro_export]
+macro_rules! render_field_read_type {
+    ($field_type:ident) => {
+        $field_type
+    };
+    ( enum:$field_type:ident) => {
+        Result<super::super::$field_type, u32>
+    };
+}
+
 #[macro_export]
 macro_rules! create_getters {
-    ($($r_field:ident($r_field_mask:expr, $($r_field_type:tt)*), )*) => {
+    ($reg_size:ident; $($(#[$r_doc:meta])* $r_field:ident($r_field_mask:expr, $($r_field_type:tt)*) $([$($r_range:tt)*])?, )*) => {
         $(
+        $(#[$r_doc])*
         #[allow(non_snake_case)]
-        pub fn $r_field(&self) -> render_field_type!($($r_field_type)*) {
-            let mask : u32 = $r_field_mask;
-            let raw_value = (self.value & mask >> mask.trailing_zeros());
+        pub fn $r_field(&self) -> render_field_read_type!($($r_field_type)*) {
+            let mask : $reg_size = $r_field_mask;
+            let raw_value = (self.value & mask) >> mask.trailing_zeros();
             render_field_type_converter!($($r_field_type)*, raw_value)
         }
         )*
     };
 }
 
+/// `Debug` and, with the `defmt` feature, `defmt::Format` of the Reader, listing the decoded
+/// value of every readable field
+#[macro_export]
+macro_rules! create_formatters {
+    ($reg:ident; $($(#[$r_doc:meta])* $r_field:ident($r_field_mask:expr, $($r_field_type:tt)*) $([$($r_range:tt)*])?, )*) => {
+        impl core::fmt::Debug for Reader {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                let mut debug = f.debug_struct(stringify!($reg));
+                debug.field("bits", &format_args!("{:#X}", self.value));
+                $(
+                format_field!(debug, $r_field, self.$r_field(), $($r_field_type)*);
+                )*
+                debug.finish()
+            }
+        }
+        #[cfg(feature = "defmt")]
+        impl defmt::Format for Reader {
+            fn format(&self, f: defmt::Formatter) {
+                defmt::write!(f, "{=str} {{ bits: {=u64:#X}", stringify!($reg), self.value as u64);
+                $(
+                format_field!(defmt f, $r_field, self.$r_field(), $($r_field_type)*);
+                )*
+                defmt::write!(f, " }}");
+            }
+        }
+    };
+}
+
+/// One field of the formatters, enumerated values without variant are shown as raw value
+#[macro_export]
+macro_rules! format_field {
+    (defmt $f:ident, $field:ident, $value:expr, enum:$field_type:ident) => {
+        match $value {
+            Ok(variant) => defmt::write!($f, ", {=str}: {}", stringify!($field), variant),
+            Err(raw) => defmt::write!($f, ", {=str}: {=u32:#X}", stringify!($field), raw),
+        }
+    };
+    (defmt $f:ident, $field:ident, $value:expr, $field_type:ident) => {
+        defmt::write!($f, ", {=str}: {}", stringify!($field), $value)
+    };
+    ($debug:ident, $field:ident, $value:expr, enum:$field_type:ident) => {
+        match $value {
+            Ok(variant) => $debug.field(stringify!($field), &variant),
+            Err(raw) => $debug.field(stringify!($field), &format_args!("{:#X}", raw)),
+        };
+    };
+    ($debug:ident, $field:ident, $value:expr, $field_type:ident) => {
+        $debug.field(stringify!($field), &$value);
+    };
+}
+
 #[macro_export]
 macro_rules! create_setters {
-    ($($w_field:ident($w_field_mask:expr, $($w_field_type:tt)*), )*) => {
+    ($reg_size:ident; $($(#[$w_doc:meta])* $w_field:ident($w_field_mask:expr, $($w_field_type:tt)*) $([$w_minimum:expr, $w_maximum:expr])?, )*) => {
         $(
+        $(#[$w_doc])*
         #[allow(non_snake_case)]
         pub fn $w_field(&mut self, value: render_field_type!($($w_field_type)*)) {
-            let mask : u32 = $w_field_mask;
-            self.value = (self.value & !mask) | (((value as u32)  << mask.trailing_zeros() ) & mask);
+            let mask : $reg_size = $w_field_mask;
+            let value = value as $reg_size;
+            // Values outside of the allowed range panic in debug builds and saturate otherwise
+            $(
+            let (minimum, maximum): ($reg_size, $reg_size) = ($w_minimum, $w_maximum);
+            debug_assert!(
+                value >= minimum && value <= maximum,
+                concat!(stringify!($w_field), " out of range")
+            );
+            let value = value.max(minimum).min(maximum);
+            )?
+            self.value = (self.value & !mask) | ((value << mask.trailing_zeros()) & mask);
             self.mask = self.mask | mask;
         }
         )*
@@ -50,55 +124,485 @@ macro_rules! create_setters {
 }
 
 #[macro_export]
-macro_rules! create_reg {
-    ($peripheral:ident::$reg:ident($reg_size:ident) =>
+macro_rules! optional_value {
+    () => {
+        None
+    };
+    ($value:expr) => {
+        Some($value)
+    };
+}
+
+/// `clear_*` methods of fields that are cleared by writing `$clear_value` into them
+#[macro_export]
+macro_rules! create_clearers {
+    ($reg_size:ident; $($(#[$c_doc:meta])* $clear_fn:ident($c_field_mask:expr, $clear_value:literal), )*) => {
+        $(
+        $(#[$c_doc])*
+        #[allow(non_snake_case)]
+        pub fn $clear_fn(&mut self) {
+            let mask : $reg_size = $c_field_mask;
+            self.value = if $clear_value == 0 { self.value & !mask } else { self.value | mask };
+            self.mask = self.mask | mask;
+        }
+        )*
+    };
+}
+
+/// Mask, offset and width of every field as associated constants of an uninhabited type
+#[macro_export]
+macro_rules! create_field_consts {
+    ($reg_size:ident; $($(#[$f_doc:meta])* $f_field:ident($f_field_mask:expr), )*) => {
+        $(
+        $(#[$f_doc])*
+        pub enum $f_field {}
+        impl $f_field {
+            /// Bits of the field within the register
+            pub const MASK: $reg_size = $f_field_mask;
+            /// Position of the least significant bit of the field
+            pub const OFFSET: u32 = Self::MASK.trailing_zeros();
+            /// Number of bits of the field
+            pub const WIDTH: u32 = Self::MASK.count_ones();
+        }
+        )*
+    };
+}
+
+/// `write` of the Writer and `modify_address` for the read-modify-write policy of the register:
+/// plain, in a critical section, through the bit-band alias or through a set and a clear register
+#[macro_export]
+macro_rules! create_atomic {
+    (@read_merge_write $reg_size:ident; $section:ident) => {
+        impl Writer{
+            /// Writes the fields that were set, the other bits keep their value.
+            /// Registers with READ_ACTION are not read: their other bits are written with the reset
+            /// value (WRITE_WITHOUT_READ), nothing is written if no field was set.
+            pub fn write(&self) {
+                create_atomic!(@$section {
+                    unsafe{ self.read_merge_write() }
+                })
+            }
+            /// Reads the register unless it is not needed and writes the merged value
+            unsafe fn read_merge_write(&self) {
+                let value: $reg_size = match WRITE_WITHOUT_READ {
+                    // The reset value would only overwrite the fields
+                    Some(_) if self.mask == 0 => return,
+                    Some(value) => value,
+                    None if self.mask == !0 => 0,
+                    None => crate::access::read(self.address),
+                };
+                crate::access::write(self.address, self.merge(value), self.mask);
+            }
+            /// Value to write for the register value `current`: the bits set by this Writer,
+            /// every other bit as read unless writing it back would change the register
+            fn merge(&self, current: $reg_size) -> $reg_size {
+                let untouched = !self.mask;
+                let current = (current & !WRITE_ZEROS_MASK) | WRITE_ONES_MASK;
+                (current & untouched) | (self.value & self.mask)
+            }
+        }
+        /// Reads the register at `address` once, lets `f` compute the new value from the current
+        /// one and writes the result back once. The Writer passed to `f` starts with the value read.
+        /// This read triggers the side effects of registers with READ_ACTION.
+        /// The caller has to make sure that `address` points to such a register.
+        pub unsafe fn modify_address<F>(address: *mut $reg_size, f: F)
+        where
+            F: FnOnce(&Reader, &mut Writer),
+        {
+            create_atomic!(@$section {
+                let value: $reg_size = crate::access::read(address);
+                let reader = Reader{value};
+                let mut writer = Writer{value, mask: 0, address};
+                f(&reader, &mut writer);
+                crate::access::write(address, writer.merge(value), writer.mask);
+            })
+        }
+    };
+    (@plain {$($body:tt)*}) => {
+        { $($body)* }
+    };
+    (@critical_section {$($body:tt)*}) => {
+        critical_section::with(|_| { $($body)* })
+    };
+    (@write_bits $reg_size:ident; BIT_BAND) => {
+        /// Writes the bits that were set one by one through the bit-band alias of the register,
+        /// the hardware performs each of them as an atomic read-modify-write
+        unsafe fn write_bits(&self) {
+            let address = self.address as usize;
+            let alias = (address & 0xF000_0000) + 0x0200_0000 + (address & 0x000F_FFFF) * 32;
+            let mut mask = self.mask;
+            while mask != 0 {
+                let bit = mask.trailing_zeros() as usize;
+                crate::access::write((alias + bit * 4) as *mut u32, ((self.value >> bit) & 1) as u32, 1);
+                mask &= mask - 1;
+            }
+        }
+    };
+    (@write_bits $reg_size:ident; SET_CLEAR($set_offset:expr, $clear_offset:expr, $clear_shift:expr)) => {
+        /// Writes the ones that were set into the set register and the zeros into the clear register
+        unsafe fn write_bits(&self) {
+            let set = (self.address as *mut u8).offset($set_offset) as *mut $reg_size;
+            let clear = (self.address as *mut u8).offset($clear_offset) as *mut $reg_size;
+            let set_bits = self.value & self.mask;
+            let clear_bits = (!self.value & self.mask) << $clear_shift;
+            if set == clear {
+                crate::access::write(set, set_bits | clear_bits, set_bits | clear_bits);
+            } else {
+                if set_bits != 0 {
+                    crate::access::write(set, set_bits, set_bits);
+                }
+                if clear_bits != 0 {
+                    crate::access::write(clear, clear_bits, clear_bits);
+                }
+            }
+        }
+    };
+    ($reg_size:ident;) => {
+        create_atomic!(@read_merge_write $reg_size; plain);
+    };
+    ($reg_size:ident; CRITICAL_SECTION) => {
+        create_atomic!(@read_merge_write $reg_size; critical_section);
+    };
+    ($reg_size:ident; $($policy:tt)+) => {
+        impl Writer{
+            /// Writes the fields that were set, the other bits keep their value.
+            /// The register is not read, only the bits that were set are written.
+            pub fn write(&self) {
+                unsafe{ self.write_bits() }
+            }
+            create_atomic!(@write_bits $reg_size; $($policy)+);
+        }
+        /// Reads the register at `address` once and lets `f` compute the new value from the
+        /// current one. Only the fields set by `f` are written, the other bits are not touched.
+        /// This read triggers the side effects of registers with READ_ACTION.
+        /// The caller has to make sure that `address` points to such a register.
+        pub unsafe fn modify_address<F>(address: *mut $reg_size, f: F)
+        where
+            F: FnOnce(&Reader, &mut Writer),
+        {
+            let value: $reg_size = crate::access::read(address);
+            let reader = Reader{value};
+            let mut writer = Writer{value, mask: 0, address};
+            f(&reader, &mut writer);
+            writer.write_bits();
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! create_reg_types {
+    ($reg:ident; $reg_size:ident =>
             $(RW{$($rw_tts:tt)+})?
             $(R{$($r_tts:tt)+})?
             $(W{$($w_tts:tt)+})?
+            $(POP{$($pop_tts:tt)+})?
+            $(CLEAR{$($clear_tts:tt)+})?
+            $(WRITE_ZEROS($write_zeros:expr))?
+            $(WRITE_ONES($write_ones:expr))?
+            $(READ_ACTION($unread_value:expr))?
+            $(RESET($reset_value:expr, $reset_mask:expr))?
+            $(ATOMIC($($atomic:tt)+))?
+            $(FIELDS{$($fields_tts:tt)+})?
     ) => {
-        pub mod $reg{
-            pub fn new() -> Writer {
-                Writer::new()
+        create_field_consts!{$reg_size; $($($fields_tts)*)?}
+
+        /// Value of the register after reset
+        pub const RESET_VALUE: $reg_size = 0 $(| $reset_value)?;
+        /// Bits of the register that have a defined reset value
+        pub const RESET_MASK: $reg_size = 0 $(| $reset_mask)?;
+        /// Bits that are written as zeros if they are not set explicitly, e.g. write-1-to-clear flags
+        pub const WRITE_ZEROS_MASK: $reg_size = 0 $(| $write_zeros)?;
+        /// Bits that are written as ones if they are not set explicitly, e.g. write-0-to-clear flags
+        pub const WRITE_ONES_MASK: $reg_size = 0 $(| $write_ones)?;
+        /// Reading the register has side effects (readAction), so `Writer::write` does not read it
+        /// and writes the bits that are not set explicitly with this value (the reset value).
+        /// Fields that are not set lose their value.
+        pub const WRITE_WITHOUT_READ: Option<$reg_size> = optional_value!($($unread_value)?);
+
+        /// Collects field values, `write` stores them in the register
+        pub struct Writer{
+            value: $reg_size,
+            mask: $reg_size,
+            address: *mut $reg_size,
+        }
+        impl Writer{
+            /// Writer for the register at `address`.
+            /// The caller has to make sure that `address` points to such a register.
+            pub unsafe fn from_address(address: *mut $reg_size) -> Writer {
+                Writer{value: 0, mask: 0, address}
+            }
+            /// Writer for the register at `address` that starts from RESET_VALUE and writes all bits,
+            /// so the register is not read. The caller has to make sure that `address` points to such a register.
+            pub unsafe fn reset_from_address(address: *mut $reg_size) -> Writer {
+                Writer{value: RESET_VALUE, mask: !0, address}
+            }
+            /// Sets the raw value of the whole register.
+            /// The caller has to make sure that `bits` is a valid value for the register.
+            pub unsafe fn bits(&mut self, bits: $reg_size) {
+                self.value = bits;
+                self.mask = !0;
+            }
+            /// Bits that were set explicitly, `write` keeps the value of the other bits
+            pub fn mask(&self) -> $reg_size {
+                self.mask
             }
-            pub fn read() -> Reader {
-                Reader::new()
+            /*
+            Implementation of setters
+             */
+             create_setters!{$reg_size; $($($rw_tts)*)? $($($w_tts)*)?}
+             create_clearers!{$reg_size; $($($clear_tts)*)?}
+        }
+        /// Value of the register at the time it was read
+        pub struct Reader{
+            value: $reg_size
+        }
+        create_atomic!{$reg_size; $($($atomic)*)?}
+        impl Reader{
+            /// Reads the register at `address`.
+            /// The caller has to make sure that `address` points to such a register.
+            pub unsafe fn from_address(address: *const $reg_size) -> Reader {
+                Reader{ value: crate::access::read(address)}
+            }
+            /// Raw value of the whole register
+            pub fn bits(&self) -> $reg_size {
+                self.value
             }
+            /*
+            Implementation of accessors
+             */
+            create_getters!($reg_size; $($($r_tts)*)? $($($rw_tts)*)? $($($pop_tts)*)?);
+        }
+        create_formatters!{$reg; $($($r_tts)*)? $($($rw_tts)*)? $($($pop_tts)*)?}
+    };
+}
 
-            pub struct Writer{
-                value: u32,
-                mask: u32,
+/// Register module with its access functions. With `in Token` the access functions take the
+/// ownership token of the peripheral module, `&Token` to read and `&mut Token` to write.
+#[macro_export]
+macro_rules! create_reg {
+    ($(#[$doc:meta])* $peripheral:ident::$reg:ident($reg_size:ident) @ $address:literal $(in $token:ident)? => $($fields:tt)*) => {
+        $(#[$doc])*
+        pub mod $reg{
+            /// Address of the register, e.g. for the peripheral address of DMA transfers
+            pub const ADDRESS: usize = $address;
+            /// Address of the register, e.g. for the peripheral address of DMA transfers
+            pub const fn address() -> usize {
+                ADDRESS
             }
-            impl Writer{
-                pub fn new() -> Writer {
-                    Writer{value: 0, mask: 0}
+            /// Writer for the register, nothing is written until `write` is called
+            pub fn new($(_: &mut super::$token)?) -> Writer {
+                unsafe{
+                    Writer::from_block(super::ptr())
+                }
+            }
+            /// Reads the register
+            pub fn read($(_: &super::$token)?) -> Reader {
+                unsafe{
+                    Reader::from_block(super::ptr())
+                }
+            }
+            /// Writer that starts from the reset value, all bits are written by `write`
+            pub fn reset($(_: &mut super::$token)?) -> Writer {
+                unsafe{
+                    Writer::reset_from_address(core::ptr::addr_of_mut!((*super::ptr()).$reg))
                 }
-                pub fn write(&self) {
-                    unsafe{
-                        let mut value = core::ptr::read_volatile(&crate::peripherals::$peripheral.$reg);
-                        value = (value & !self.mask) | (self.value & self.mask);
-                        core::ptr::write_volatile(&mut crate::peripherals::$peripheral.$reg, value);
-                    }
+            }
+            /// Writes the reset value into the register
+            pub fn write_reset($(_: &mut super::$token)?) {
+                unsafe{
+                    Writer::reset_from_address(core::ptr::addr_of_mut!((*super::ptr()).$reg))
+                }.write()
+            }
+            /// Read-modify-write with a single volatile read and a single volatile write
+            pub fn modify<F>($(_: &mut super::$token,)? f: F)
+            where
+                F: FnOnce(&Reader, &mut Writer),
+            {
+                unsafe{
+                    modify_address(core::ptr::addr_of_mut!((*super::ptr()).$reg), f)
                 }
-                /*
-                Implementation of setters
-                 */
-                 create_setters!{$($($rw_tts)*)? $($($w_tts)*)?}
             }
-            pub struct Reader{
-                value: u32
+
+            create_reg_types!{$reg; $reg_size => $($fields)*}
+
+            // The Reader and Writer are shared with derived peripherals, so they get no
+            // constructors bound to this instance, `new` and `read` of the module are the ones
+            impl Writer{
+                /// Writer for the register inside another instance of the register block.
+                /// The caller has to make sure that `block` points to such an instance.
+                pub unsafe fn from_block(block: *mut super::RegisterBlock) -> Writer {
+                    Writer::from_address(core::ptr::addr_of_mut!((*block).$reg))
+                }
             }
             impl Reader{
-                pub fn new() -> Reader {
-                    unsafe{
-                        Reader{ value: core::ptr::read_volatile(&crate::peripherals::$peripheral.$reg)}
-                    }
+                /// Reads the register inside another instance of the register block.
+                /// The caller has to make sure that `block` points to such an instance.
+                pub unsafe fn from_block(block: *const super::RegisterBlock) -> Reader {
+                    Reader::from_address(core::ptr::addr_of!((*block).$reg))
                 }
-                /*
-                Implementation of accessors
-                 */
-                create_getters!($($($r_tts)*)? $($($rw_tts)*)?);
             }
         }
         //create_ordered_reg!{$peripheral::$reg($reg_size) => R{$($($r_tts)*)? $($($rw_tts)*)?} W{$($($rw_tts)*)? $($($w_tts)*)?}}
     };
+    // Register array, $first is the struct field of the first element
+    ($(#[$doc:meta])* $peripheral:ident::$reg:ident[$first:ident; $dim:literal; $increment:literal]($reg_size:ident) @ $address:literal $(in $token:ident)? => $($fields:tt)*) => {
+        $(#[$doc])*
+        pub mod $reg{
+            /// Number of registers in the array
+            pub const COUNT: usize = $dim;
+            /// Distance of the elements in bytes
+            pub const INCREMENT: usize = $increment;
+            /// Address of the first element
+            pub const ADDRESS: usize = $address;
+            /// Address of the element `index`, e.g. for the peripheral address of DMA transfers
+            pub const fn address(index: usize) -> usize {
+                assert!(index < COUNT, "index out of bounds");
+                ADDRESS + index * INCREMENT
+            }
+
+            /// Writer for the element `index`, nothing is written until `write` is called
+            pub fn new($(_: &mut super::$token,)? index: usize) -> Writer {
+                unsafe{
+                    Writer::from_address(element(super::ptr(), index))
+                }
+            }
+            /// Reads the element `index`
+            pub fn read($(_: &super::$token,)? index: usize) -> Reader {
+                unsafe{
+                    Reader::from_address(element(super::ptr(), index))
+                }
+            }
+            /// Writer for the element `index` that starts from the reset value, all bits are written
+            pub fn reset($(_: &mut super::$token,)? index: usize) -> Writer {
+                unsafe{
+                    Writer::reset_from_address(element(super::ptr(), index))
+                }
+            }
+            /// Writes the reset value into the element `index`
+            pub fn write_reset($(_: &mut super::$token,)? index: usize) {
+                unsafe{
+                    Writer::reset_from_address(element(super::ptr(), index))
+                }.write()
+            }
+            /// Read-modify-write of the element `index` with a single volatile read and write
+            pub fn modify<F>($(_: &mut super::$token,)? index: usize, f: F)
+            where
+                F: FnOnce(&Reader, &mut Writer),
+            {
+                unsafe{
+                    modify_address(element(super::ptr(), index), f)
+                }
+            }
+
+            /// Address of the array element `index` inside an instance of the register block.
+            /// The caller has to make sure that `block` points to such an instance.
+            pub unsafe fn element(block: *mut super::RegisterBlock, index: usize) -> *mut $reg_size {
+                assert!(index < COUNT, "index out of bounds");
+                let first = core::ptr::addr_of_mut!((*block).$first) as *mut u8;
+                first.add(index * INCREMENT) as *mut $reg_size
+            }
+
+            create_reg_types!{$reg; $reg_size => $($fields)*}
+        }
+    };
+}
+
+/// Register of a derived peripheral that shares the Reader/Writer types of its base peripheral
+#[macro_export]
+macro_rules! create_derived_reg {
+    ($(#[$doc:meta])* $peripheral:ident::$reg:ident @ $address:literal $(in $token:ident)? => $base:ident) => {
+        $(#[$doc])*
+        pub mod $reg{
+            pub use super::super::$base::$reg::*;
+
+            /// Address of the register, e.g. for the peripheral address of DMA transfers
+            pub const ADDRESS: usize = $address;
+            /// Address of the register, e.g. for the peripheral address of DMA transfers
+            pub const fn address() -> usize {
+                ADDRESS
+            }
+
+            /// Writer for the register, nothing is written until `write` is called
+            pub fn new($(_: &mut super::$token)?) -> Writer {
+                unsafe{
+                    Writer::from_block(super::ptr())
+                }
+            }
+            /// Reads the register
+            pub fn read($(_: &super::$token)?) -> Reader {
+                unsafe{
+                    Reader::from_block(super::ptr())
+                }
+            }
+            /// Writer that starts from the reset value, all bits are written by `write`
+            pub fn reset($(_: &mut super::$token)?) -> Writer {
+                unsafe{
+                    Writer::reset_from_address(core::ptr::addr_of_mut!((*super::ptr()).$reg))
+                }
+            }
+            /// Writes the reset value into the register
+            pub fn write_reset($(_: &mut super::$token)?) {
+                unsafe{
+                    Writer::reset_from_address(core::ptr::addr_of_mut!((*super::ptr()).$reg))
+                }.write()
+            }
+            /// Read-modify-write with a single volatile read and a single volatile write
+            pub fn modify<F>($(_: &mut super::$token,)? f: F)
+            where
+                F: FnOnce(&Reader, &mut Writer),
+            {
+                unsafe{
+                    modify_address(core::ptr::addr_of_mut!((*super::ptr()).$reg), f)
+                }
+            }
+        }
+    };
+    ($(#[$doc:meta])* $peripheral:ident::$reg:ident[] @ $address:literal $(in $token:ident)? => $base:ident) => {
+        $(#[$doc])*
+        pub mod $reg{
+            pub use super::super::$base::$reg::*;
+
+            /// Address of the first element
+            pub const ADDRESS: usize = $address;
+            /// Address of the element `index`, e.g. for the peripheral address of DMA transfers
+            pub const fn address(index: usize) -> usize {
+                assert!(index < COUNT, "index out of bounds");
+                ADDRESS + index * INCREMENT
+            }
+
+            /// Writer for the element `index`, nothing is written until `write` is called
+            pub fn new($(_: &mut super::$token,)? index: usize) -> Writer {
+                unsafe{
+                    Writer::from_address(element(super::ptr(), index))
+                }
+            }
+            /// Reads the element `index`
+            pub fn read($(_: &super::$token,)? index: usize) -> Reader {
+                unsafe{
+                    Reader::from_address(element(super::ptr(), index))
+                }
+            }
+            /// Writer for the element `index` that starts from the reset value, all bits are written
+            pub fn reset($(_: &mut super::$token,)? index: usize) -> Writer {
+                unsafe{
+                    Writer::reset_from_address(element(super::ptr(), index))
+                }
+            }
+            /// Writes the reset value into the element `index`
+            pub fn write_reset($(_: &mut super::$token,)? index: usize) {
+                unsafe{
+                    Writer::reset_from_address(element(super::ptr(), index))
+                }.write()
+            }
+            /// Read-modify-write of the element `index` with a single volatile read and write
+            pub fn modify<F>($(_: &mut super::$token,)? index: usize, f: F)
+            where
+                F: FnOnce(&Reader, &mut Writer),
+            {
+                unsafe{
+                    modify_address(element(super::ptr(), index), f)
+                }
+            }
+        }
+    };
 }
@@ -1,4 +1,21 @@
+mod arrays;
+mod atomic;
+mod constraints;
+mod cpu;
+mod derived;
+mod docs;
+mod entities;
+mod enums;
+mod interrupts;
+mod layout;
+mod model;
+#[allow(clippy::module_inception)]
 mod output;
+mod read_actions;
+mod reference;
+mod register_map;
 mod templates;
+mod walk;
+mod write_values;
 
 pub use output::Output;
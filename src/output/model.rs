@@ -0,0 +1,241 @@
+use super::entities::HalEntity;
+use super::walk;
+
+use serde_derive::Serialize;
+use serde_json::Value;
+
+/// The resolved registers and entities the crate is generated from, written as model.json for
+/// tooling. Unlike the annotated device it holds no template data (rustdoc lines, layouts, ...).
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    pub name: String,
+    pub description: Option<String>,
+    pub cpu: Option<String>,
+    /// Derived peripherals are expanded, with their own base address and registers
+    pub peripherals: Vec<ModelPeripheral>,
+    pub interrupts: Vec<ModelInterrupt>,
+    /// Enumerated types referred to by `enumeratedType` of the fields
+    pub enumerated_types: Vec<ModelEnum>,
+    pub entities: Vec<ModelEntity>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPeripheral {
+    pub name: String,
+    pub derived_from: Option<String>,
+    pub description: Option<String>,
+    pub base_address: u64,
+    pub interrupts: Vec<ModelInterrupt>,
+    pub registers: Vec<ModelRegister>,
+}
+
+#[derive(Serialize)]
+pub struct ModelInterrupt {
+    pub name: String,
+    pub value: u64,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRegister {
+    pub name: String,
+    pub description: Option<String>,
+    /// Offset and address of the register, of its first element for register arrays. The offset
+    /// is relative to the peripheral, also for registers of clusters.
+    pub address_offset: u64,
+    pub address: u64,
+    pub size: u64,
+    pub access: Option<String>,
+    pub reset_value: u64,
+    pub reset_mask: u64,
+    /// Bits written as 0 resp. 1 to leave them unchanged, see write_values::annotate
+    pub write_zeros_mask: u64,
+    pub write_ones_mask: u64,
+    pub read_action: Option<String>,
+    /// Elements of register arrays, empty for plain registers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub elements: Vec<ModelElement>,
+    pub fields: Vec<ModelField>,
+}
+
+#[derive(Serialize)]
+pub struct ModelElement {
+    pub name: String,
+    pub address: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelField {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u64,
+    pub bit_width: u64,
+    pub mask: u64,
+    pub access: Option<String>,
+    pub enumerated_type: Option<String>,
+    pub modified_write_values: Option<String>,
+    pub read_action: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ModelEnum {
+    pub name: String,
+    pub description: Option<String>,
+    pub values: Vec<ModelEnumValue>,
+}
+
+#[derive(Serialize)]
+pub struct ModelEnumValue {
+    pub name: String,
+    pub value: u64,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ModelEntity {
+    pub name: String,
+    pub fields: Vec<ModelEntityField>,
+}
+
+/// An entity field and the svd field it maps to
+#[derive(Serialize)]
+pub struct ModelEntityField {
+    pub name: String,
+    pub peripheral: String,
+    /// Register of the field, the element for register arrays
+    pub register: String,
+    pub field: String,
+    pub address: u64,
+    pub mask: u64,
+    pub visible: bool,
+}
+
+pub fn resolve(device: &Value, hal_entities: &[HalEntity]) -> Model {
+    Model {
+        name: string(&device["name"]).unwrap_or_default(),
+        description: string(&device["description"]),
+        cpu: string(&device["cpu"]["name"]),
+        peripherals: walk::peripherals(device).map(peripheral).collect(),
+        interrupts: list(&device["interrupts"]).map(interrupt).collect(),
+        enumerated_types: list(&device["enumeratedTypes"])
+            .map(|enumerated_type| ModelEnum {
+                name: string(&enumerated_type["name"]).unwrap_or_default(),
+                description: string(&enumerated_type["description"]),
+                values: list(&enumerated_type["values"])
+                    .map(|value| ModelEnumValue {
+                        name: string(&value["name"]).unwrap_or_default(),
+                        value: number(&value["value"]),
+                        description: string(&value["description"]),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        entities: hal_entities.iter().map(entity).collect(),
+    }
+}
+
+fn peripheral(peripheral: &Value) -> ModelPeripheral {
+    ModelPeripheral {
+        name: string(&peripheral["name"]).unwrap_or_default(),
+        derived_from: string(&peripheral["derivedFrom"]),
+        description: string(&peripheral["description"]),
+        base_address: number(&peripheral["baseAddress"]),
+        interrupts: list(&peripheral["interrupts"]).map(interrupt).collect(),
+        registers: walk::registers(peripheral).map(register).collect(),
+    }
+}
+
+fn interrupt(interrupt: &Value) -> ModelInterrupt {
+    ModelInterrupt {
+        name: string(&interrupt["name"]).unwrap_or_default(),
+        value: number(&interrupt["value"]),
+        description: string(&interrupt["description"]),
+    }
+}
+
+fn register(register: &Value) -> ModelRegister {
+    // Plain registers are their only element
+    let elements = if register["dim"].is_null() {
+        vec![]
+    } else {
+        list(&register["elements"])
+            .map(|element| ModelElement {
+                name: string(&element["name"]).unwrap_or_default(),
+                address: number(&element["address"]),
+            })
+            .collect()
+    };
+    ModelRegister {
+        name: string(&register["name"]).unwrap_or_default(),
+        description: string(&register["description"]),
+        address_offset: number(&register["elements"][0]["addressOffset"]),
+        address: number(&register["address"]),
+        size: number(&register["size"]),
+        access: string(&register["access"]),
+        reset_value: number(&register["resetValue"]),
+        reset_mask: number(&register["resetMask"]),
+        write_zeros_mask: number(&register["writeZerosMask"]),
+        write_ones_mask: number(&register["writeOnesMask"]),
+        read_action: string(&register["readAction"]),
+        elements,
+        fields: walk::fields(register).map(field).collect(),
+    }
+}
+
+fn field(field: &Value) -> ModelField {
+    ModelField {
+        name: string(&field["name"]).unwrap_or_default(),
+        description: string(&field["description"]),
+        bit_offset: number(&field["bitOffset"]),
+        bit_width: number(&field["bitWidth"]),
+        mask: number(&field["mask"]),
+        access: string(&field["access"]),
+        enumerated_type: string(&field["fieldType"]["enum"]["typeName"]),
+        modified_write_values: string(&field["modifiedWriteValues"]),
+        read_action: string(&field["readAction"]),
+    }
+}
+
+fn entity(entity: &HalEntity) -> ModelEntity {
+    let mut fields = vec![];
+    for peripheral in &entity.peripherals {
+        for register in &peripheral.registers {
+            let register_fields = register
+                .read_write_fields
+                .iter()
+                .chain(&register.read_fields)
+                .chain(&register.write_fields);
+            for field in register_fields {
+                fields.push(ModelEntityField {
+                    name: field.name.clone(),
+                    peripheral: peripheral.name.clone(),
+                    register: register.name.clone(),
+                    field: field.svd_name.clone(),
+                    address: number(&register.address),
+                    mask: number(&field.mask),
+                    visible: field.visible,
+                });
+            }
+        }
+    }
+    ModelEntity {
+        name: entity.name.clone(),
+        fields,
+    }
+}
+
+fn list(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(String::from)
+}
+
+fn number(value: &Value) -> u64 {
+    value.as_u64().unwrap_or_default()
+}
@@ -1,5 +1,20 @@
 use super::super::input;
+use super::arrays;
+use super::atomic;
+use super::constraints;
+use super::cpu;
+use super::derived;
+use super::docs;
+use super::entities;
+use super::enums;
+use super::interrupts;
+use super::layout;
+use super::model;
+use super::read_actions;
+use super::reference;
+use super::register_map;
 use super::templates;
+use super::write_values;
 
 use serde_derive::Serialize;
 use std::fs;
@@ -8,15 +23,104 @@ use std::io::Write;
 
 pub struct Output {
     pub svd: patch_svd::Svd,
+    /// Serialized svd device as seen by the templates
+    pub device: serde_json::Value,
+    pub hal_entities: Vec<entities::HalEntity>,
 }
 
 impl Output {
     pub fn from(input: &mut input::Input) -> Output {
+        let mut device = serde_json::to_value(&input.svd.device).unwrap();
+        derived::annotate(&mut device);
+        arrays::annotate(&mut device);
+        layout::annotate(&mut device);
+        enums::annotate(&mut device);
+        write_values::annotate(&mut device);
+        read_actions::annotate(&mut device);
+        constraints::annotate(&mut device);
+        atomic::annotate(&mut device, &input.hal_definition.atomic);
+        register_map::annotate(&mut device);
+        interrupts::annotate(&mut device);
+        cpu::annotate(&mut device);
+        docs::annotate(&mut device);
+        reference::annotate(&mut device);
+        device["peripheralAccess"] = serde_json::to_value(input.hal_definition.peripheral_access).unwrap();
+        device["peripheralTokens"] = serde_json::json!(input.hal_definition.peripheral_tokens);
         Output {
             svd: input.svd.clone(),
+            hal_entities: entities::resolve(&input.hal_definition.entities, &device),
+            device,
         }
     }
 
+    /// Writes a C header with the base addresses, register block structs and field positions and
+    /// masks of the same patched svd as `write`, into the include directory of the crate.
+    pub fn write_c_header(&self, output_path: String) {
+        let device_name = self.svd.device.name.to_ascii_lowercase();
+        let include_dir_path = output_path + "rawhal-" + device_name.as_str() + "/include";
+        if fs::create_dir_all(&include_dir_path).is_err() {
+            panic!("Could not create directory {}", include_dir_path);
+        }
+        templates::render_template_into_path(
+            templates::C_HEADER_TEMPLATE,
+            &self.device,
+            &(include_dir_path + "/" + device_name.as_str() + ".h"),
+        );
+    }
+
+    /// Writes the register reference of the patched svd as markdown into the doc directory of
+    /// the crate, so that the effect of svd patches can be reviewed.
+    pub fn write_reference(&self, output_path: String) {
+        let device_name = self.svd.device.name.to_ascii_lowercase();
+        let doc_dir_path = output_path + "rawhal-" + device_name.as_str() + "/doc";
+        if fs::create_dir_all(&doc_dir_path).is_err() {
+            panic!("Could not create directory {}", doc_dir_path);
+        }
+        templates::render_template_into_path(
+            templates::REFERENCE_TEMPLATE,
+            &self.device,
+            &(doc_dir_path + "/registers.md"),
+        );
+    }
+
+    /// Writes the resolved model the crate is generated from as model.json into the crate: the
+    /// peripherals with derived ones expanded, their registers and fields with addresses, masks
+    /// and enumerated types, and the svd fields the entities map to.
+    pub fn write_model(&self, output_path: String) {
+        let model = model::resolve(&self.device, &self.hal_entities);
+        let device_name = self.svd.device.name.to_ascii_lowercase();
+        let model_path = output_path + "rawhal-" + device_name.as_str() + "/model.json";
+        let file = File::create(&model_path).unwrap();
+        serde_json::to_writer_pretty(file, &model).unwrap();
+    }
+
+    /// Short description of the generated crate for the user
+    pub fn summary(&self) -> String {
+        let device_name = self.svd.device.name.clone();
+        let mut summary = format!(
+            "Generated rawhal-{} with {} peripherals, {} interrupts and {} entities",
+            device_name.to_ascii_lowercase(),
+            self.device["peripherals"].as_array().map_or(0, |p| p.len()),
+            self.device["interrupts"].as_array().map_or(0, |i| i.len()),
+            self.hal_entities.len()
+        );
+        let cpu = &self.device["cpu"];
+        match cpu["targetTriple"].as_str() {
+            Some(triple) => summary += &format!(
+                "\nThe {} core matches the target {}, e.g. `rustup target add {}`",
+                cpu["name"].as_str().unwrap_or_default(),
+                triple,
+                triple
+            ),
+            None if cpu.is_object() => summary += &format!(
+                "\nNo rust target is known for the {} core",
+                cpu["name"].as_str().unwrap_or_default()
+            ),
+            None => summary += "\nThe svd has no cpu, so no rust target can be suggested",
+        }
+        summary
+    }
+
     pub fn write(&self, output_path: String) {
         let device_name = self.svd.device.name.clone();
         // create project directory
@@ -30,19 +134,37 @@ impl Output {
             #[derive(Serialize)]
             struct Content {
                 project_name: String,
+                critical_section: bool,
             }
-            let content = Content { project_name };
+            let content = Content {
+                project_name,
+                critical_section: self.device["criticalSection"] == true,
+            };
             templates::render_template_into_path(
                 templates::CARGO_TOML_TEMPLATE,
                 &content,
                 &(project_dir_path.clone() + "/Cargo.toml"),
             );
         }
-        //      peripheral.x
+        //      peripheral.x, only the symbols of the register blocks need a linker script
+        if self.device["peripheralAccess"] == "symbols" {
+            templates::render_template_into_path(
+                templates::LINKER_TEMPLATE,
+                &self.device,
+                &(project_dir_path.clone() + "/peripheral.x"),
+            );
+        }
+        //      device.x
+        templates::render_template_into_path(
+            templates::DEVICE_X_TEMPLATE,
+            &self.device,
+            &(project_dir_path.clone() + "/device.x"),
+        );
+        //      build.rs
         templates::render_template_into_path(
-            templates::LINKER_TEMPLATE,
-            &self.svd.device,
-            &(project_dir_path.clone() + "/peripheral.x"),
+            templates::BUILD_RS_TEMPLATE,
+            &self.device,
+            &(project_dir_path.clone() + "/build.rs"),
         );
         //      create src directory
         let src_dir_path = project_dir_path.clone() + "/src";
@@ -53,17 +175,79 @@ impl Output {
         if fs::create_dir(&src_dir_path).is_err() {
             //panic!("Could not create project directory {}. Please check permissions, path and make sure that the project dir does not exist already.", project_dir_path);
         }
+        //              lib.rs
+        {
+            #[derive(Serialize)]
+            #[serde(rename_all = "camelCase")]
+            struct Content<'a> {
+                #[serde(flatten)]
+                device: &'a serde_json::Value,
+                hal_entities: &'a Vec<entities::HalEntity>,
+            }
+            let content = Content {
+                device: &self.device,
+                hal_entities: &self.hal_entities,
+            };
+            templates::render_template_into_path(
+                templates::SRC_LIB_RS_TEMPLATE,
+                &content,
+                &(src_dir_path.clone() + "/lib.rs"),
+            );
+        }
         //              Macros.rs
         let macro_file_content = include_bytes!("macros.rs");
         let mut macro_file =
-            File::create(src_dir_path.clone() + &"/macros.rs".to_string()).unwrap();
-        macro_file.write(macro_file_content).unwrap();
+            File::create(src_dir_path.clone() + "/macros.rs").unwrap();
+        macro_file.write_all(macro_file_content).unwrap();
+
+        //              Register accesses, real or simulated
+        templates::render_template_into_path(
+            templates::ACCESS_TEMPLATE,
+            &self.device,
+            &(src_dir_path.clone() + "/access.rs"),
+        );
+        templates::render_template_into_path(
+            templates::SIM_TEMPLATE,
+            &self.device,
+            &(src_dir_path.clone() + "/sim.rs"),
+        );
+        templates::render_template_into_path(
+            templates::TRACE_TEMPLATE,
+            &self.device,
+            &(src_dir_path.clone() + "/trace.rs"),
+        );
+
+        //              Interrupts
+        templates::render_template_into_path(
+            templates::INTERRUPTS_TEMPLATE,
+            &self.device,
+            &(src_dir_path.clone() + "/interrupts.rs"),
+        );
 
         //              Peripheral files
         templates::render_template_into_path(
             templates::PERIPHERALS_TEMPLATE,
-            &self.svd.device,
+            &self.device,
             &(src_dir_path.clone() + "/peripherals.rs"),
         );
+
+        //              HAL entities
+        {
+            #[derive(Serialize)]
+            #[serde(rename_all = "camelCase")]
+            struct Content<'a> {
+                hal_entities: &'a Vec<entities::HalEntity>,
+                peripheral_tokens: bool,
+            }
+            let content = Content {
+                hal_entities: &self.hal_entities,
+                peripheral_tokens: self.device["peripheralTokens"] == true,
+            };
+            templates::render_template_into_path(
+                templates::HAL_TEMPLATE,
+                &content,
+                &(src_dir_path.clone() + "/hal.rs"),
+            );
+        }
     }
 }
@@ -0,0 +1,28 @@
+use super::walk;
+
+use serde_json::Value;
+
+/// Marks registers that have side effects when they are read (`readAction`, e.g. clear-on-read
+/// or FIFO pop). The `readAction` of a register applies to all of its readable fields, a register
+/// with such a field gets `readAction` as well since the hardware always reads the whole register.
+pub fn annotate(device: &mut Value) {
+    for peripheral in walk::peripherals_mut(device) {
+        for register in walk::registers_mut(peripheral) {
+            let register_default = register["readAction"].clone();
+            let mut read_action = register_default.clone();
+            for field in walk::fields_mut(register, &["readWriteFields", "readFields"]) {
+                if !field["readAction"].is_string() && register_default.is_string() {
+                    field["readAction"] = register_default.clone();
+                }
+                if field["readAction"].is_string() {
+                    read_action = field["readAction"].clone();
+                }
+            }
+            if read_action.is_string() {
+                register["readAction"] = read_action;
+            } else if let Some(register) = register.as_object_mut() {
+                register.remove("readAction");
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::docs::sanitize;
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Prepares the register reference documentation: `brief` is the sanitized description of
+/// peripherals and registers, `bitFields` splits every register from the most to the least
+/// significant bit into its fields and the reserved gaps between them, with the access, reset
+/// value and enumerated values of each field.
+pub fn annotate(device: &mut Value) {
+    let enumerated_types = device["enumeratedTypes"].clone();
+    for content in walk::peripherals_mut(device) {
+        content["brief"] = json!(brief(&content["description"]));
+        for register in walk::registers_mut(content) {
+            register["brief"] = json!(brief(&register["description"]));
+            register["bitFields"] = json!(bit_fields(register, &enumerated_types));
+        }
+    }
+}
+
+fn bit_fields(register: &Value, enumerated_types: &Value) -> Vec<Value> {
+    let size = register["size"].as_u64().unwrap_or(32);
+    let reset_value = register["resetValue"].as_u64();
+    let mut fields: Vec<&Value> = walk::fields(register).collect();
+    fields.sort_by_key(|field| std::cmp::Reverse(field["mask"].as_u64().unwrap_or(0)));
+
+    let mut bit_fields = vec![];
+    let mut next_msb = size as i64 - 1;
+    for field in fields {
+        let mask = field["mask"].as_u64().unwrap_or(0);
+        if mask == 0 {
+            continue;
+        }
+        let lsb = mask.trailing_zeros() as i64;
+        let msb = 63 - mask.leading_zeros() as i64;
+        if msb < next_msb {
+            bit_fields.push(reserved(next_msb, msb + 1));
+        }
+        let enum_values = field["fieldType"]["enum"]["typeName"]
+            .as_str()
+            .and_then(|type_name| {
+                enumerated_types
+                    .as_array()?
+                    .iter()
+                    .find(|t| t["name"] == type_name)
+            })
+            .map(|t| {
+                t["values"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|value| {
+                        json!({
+                            "name": value["name"],
+                            "value": value["value"],
+                            "brief": brief(&value["description"]),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        bit_fields.push(json!({
+            "name": field["name"],
+            "msb": msb,
+            "lsb": lsb,
+            "width": msb - lsb + 1,
+            "reserved": false,
+            "access": field["access"].as_str().or_else(|| register["access"].as_str()),
+            "resetValue": reset_value.map(|reset_value| (reset_value & mask) >> lsb),
+            "brief": brief(&field["description"]),
+            "enumValues": enum_values,
+        }));
+        next_msb = next_msb.min(lsb - 1);
+    }
+    if next_msb >= 0 {
+        bit_fields.push(reserved(next_msb, 0));
+    }
+    bit_fields
+}
+
+fn reserved(msb: i64, lsb: i64) -> Value {
+    json!({"name": "", "msb": msb, "lsb": lsb, "width": msb - lsb + 1, "reserved": true})
+}
+
+fn brief(description: &Value) -> String {
+    description.as_str().map(sanitize).unwrap_or_default()
+}
@@ -0,0 +1,42 @@
+use super::walk;
+
+use serde_json::{json, Value};
+
+/// Lists every register element of the device as `registerMap` ([{address, peripheral, name, size,
+/// resetValue}], sorted by address). The simulated register file of the `host-sim` feature starts
+/// from the reset values (zero if there is none) and keeps the bits of the register size, the
+/// `trace` feature names the accessed registers. Of registers that share an address only the first
+/// one is listed.
+pub fn annotate(device: &mut Value) {
+    let mut registers: Vec<(u64, String, String, u64, u64)> = vec![];
+    for peripheral in walk::peripherals(device) {
+        let peripheral_name = peripheral["name"].as_str().unwrap();
+        for register in walk::registers(peripheral) {
+            let size = register["size"].as_u64().unwrap();
+            let reset_value = register["resetValue"].as_u64().unwrap_or(0);
+            for element in register["elements"].as_array().into_iter().flatten() {
+                registers.push((
+                    element["address"].as_u64().unwrap(),
+                    peripheral_name.to_string(),
+                    element["name"].as_str().unwrap().to_string(),
+                    size,
+                    reset_value,
+                ));
+            }
+        }
+    }
+    registers.sort_by_key(|(address, ..)| *address);
+    registers.dedup_by_key(|(address, ..)| *address);
+    device["registerMap"] = registers
+        .iter()
+        .map(|(address, peripheral, name, size, reset_value)| {
+            json!({
+                "address": address,
+                "peripheral": peripheral,
+                "name": name,
+                "size": size,
+                "resetValue": reset_value,
+            })
+        })
+        .collect();
+}
@@ -1,9 +1,10 @@
+use inflector::Inflector;
 use lazy_static::lazy_static;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
-use tera::{Context, Result, Tera, Value};
+use tera::{Context, Tera, Value};
 
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
@@ -20,8 +21,20 @@ lazy_static! {
                     "String to hex not supported for {}",
                     s
                 ))),
-                Value::Array(s) => Err(tera::Error::msg("Array to hex not supported.")),
-                Value::Object(s) => Err(tera::Error::msg("Object to hex not supported.")),
+                Value::Array(_) => Err(tera::Error::msg("Array to hex not supported.")),
+                Value::Object(_) => Err(tera::Error::msg("Object to hex not supported.")),
+            }
+        }
+        fn snake_filter(value: &Value, _arguments: &HashMap<String, Value>) -> tera::Result<Value> {
+            match value {
+                Value::String(s) => Ok(Value::String(s.to_snake_case())),
+                _ => Err(tera::Error::msg("Only strings can be converted to snake case.")),
+            }
+        }
+        fn pascal_filter(value: &Value, _arguments: &HashMap<String, Value>) -> tera::Result<Value> {
+            match value {
+                Value::String(s) => Ok(Value::String(s.to_pascal_case())),
+                _ => Err(tera::Error::msg("Only strings can be converted to pascal case.")),
             }
         }
         let mut tera = Tera::default();
@@ -29,442 +42,1148 @@ lazy_static! {
             (CARGO_TOML_TEMPLATE, CARGO_TOML_TEMPLATE_CONTENT),
             (SRC_LIB_RS_TEMPLATE, SRC_LIB_RS_TEMPLATE_CONTENT),
             (PERIPHERALS_TEMPLATE, PERIPHERALS_TEMPLATE_CONTENT),
+            (
+                PERIPHERAL_CONTENT_TEMPLATE,
+                PERIPHERAL_CONTENT_TEMPLATE_CONTENT,
+            ),
+            (PERIPHERAL_LINK_TEMPLATE, PERIPHERAL_LINK_TEMPLATE_CONTENT),
             (LINKER_TEMPLATE, LINKER_TEMPLATE_CONTENT),
+            (BUILD_RS_TEMPLATE, BUILD_RS_TEMPLATE_CONTENT),
+            (DEVICE_X_TEMPLATE, DEVICE_X_TEMPLATE_CONTENT),
+            (INTERRUPTS_TEMPLATE, INTERRUPTS_TEMPLATE_CONTENT),
+            (ACCESS_TEMPLATE, ACCESS_TEMPLATE_CONTENT),
+            (SIM_TEMPLATE, SIM_TEMPLATE_CONTENT),
+            (TRACE_TEMPLATE, TRACE_TEMPLATE_CONTENT),
+            (C_HEADER_TEMPLATE, C_HEADER_TEMPLATE_CONTENT),
+            (C_HEADER_BLOCK_TEMPLATE, C_HEADER_BLOCK_TEMPLATE_CONTENT),
+            (REFERENCE_TEMPLATE, REFERENCE_TEMPLATE_CONTENT),
+            (REFERENCE_REGISTER_TEMPLATE, REFERENCE_REGISTER_TEMPLATE_CONTENT),
             (REG_DEF_MACROS, REG_DEF_MACROS_CONTENT),
+            (HAL_TEMPLATE, HAL_TEMPLATE_CONTENT),
+            (ENUMERATED_TYPES_TEMPLATE, ENUMERATED_TYPES_TEMPLATE_CONTENT),
+            (
+                HAL_MOD_RS_ENTITY_TYPES_TEMPLATE,
+                HAL_MOD_RS_ENTITY_TYPES_TEMPLATE_CONTENT,
+            ),
+            (
+                HAL_MOD_RS_ENTITY_REG_TEMPLATE,
+                HAL_MOD_RS_ENTITY_REG_TEMPLATE_CONTENT,
+            ),
         ])
         .unwrap();
         tera.autoescape_on(vec![]);
         tera.register_filter("hex", hex_filter);
+        tera.register_filter("snake", snake_filter);
+        tera.register_filter("pascal", pascal_filter);
         tera
     };
 }
 
-pub static CARGO_TOML_TEMPLATE: &'static str = "cargo.toml";
-static CARGO_TOML_TEMPLATE_CONTENT: &'static str = "\
+pub static CARGO_TOML_TEMPLATE: &str = "cargo.toml";
+static CARGO_TOML_TEMPLATE_CONTENT: &str = "\
 [package]
 name = \"{{project_name}}\"
 version = \"0.1.0\"
 authors = [\"Alexander Huymayer <alex@peiran.de>\"]
 edition = \"2018\"
+
+[dependencies]
+cortex-m = \"0.7\"
+cortex-m-rt = { version = \"0.7\", optional = true }
+# Enables defmt::Format of the register readers and enumerated values
+defmt = { version = \"0.3\", optional = true }
+{%- if critical_section %}
+# Needs an implementation, e.g. the critical-section-single-core feature of cortex-m
+critical-section = \"1\"
+{%- endif %}
+
+[features]
+# Interrupt vector table and device.x for cortex-m-rt
+rt = [\"cortex-m-rt/device\"]
+# Registers are simulated in memory, for testing drivers on the host
+host-sim = [{% if critical_section %}\"critical-section/std\"{% endif %}]
+# Reports every register access to the function set by trace::set_tracer
+trace = []
 ";
 
-static REG_DEF_MACROS: &'static str = "reg_def_macros.rs";
-static REG_DEF_MACROS_CONTENT: &'static str = "\
+static REG_DEF_MACROS: &str = "reg_def_macros.rs";
+static REG_DEF_MACROS_CONTENT: &str = "\
 {%- macro fieldType(fieldType) -%}
         {%-if fieldType.raw-%}
             {{fieldType.raw}}
         {%-else-%}
-            enum:
-            {%-if fieldType.enum.content-%}
-                {{fieldType.enum.content.name}}
-            {%-else-%}
-                {{fieldType.enum.derived.name}}
-            {%-endif-%}
+            enum:{{fieldType.enum.typeName}}
         {%-endif-%}
-{%- endmacro input -%}";
+{%- endmacro input -%}
 
-pub static SRC_LIB_RS_TEMPLATE: &'static str = "src_lib.rs";
-static SRC_LIB_RS_TEMPLATE_CONTENT: &'static str = "\
-#![no_std]
+{%- macro rustType(fieldType) -%}
+        {%-if fieldType.raw-%}
+            {{fieldType.raw}}
+        {%-else-%}
+            {{fieldType.enum.typeName}}
+        {%-endif-%}
+{%- endmacro rustType -%}
 
-#[macro_use]
-mod macros;
-mod hal;
-mod peripherals;
+{%- macro rustReadType(fieldType) -%}
+        {%-if fieldType.raw-%}
+            {{fieldType.raw}}
+        {%-else-%}
+            Result<{{fieldType.enum.typeName}}, u32>
+        {%-endif-%}
+{%- endmacro rustReadType -%}
 
-pub use hal::*;
-";
+{#- Lines of a rustdoc comment, the item follows after indent -#}
+{%- macro doc(lines, indent=\"\") -%}
+{%- for line in lines -%}
+///{% if line %} {{line}}{% endif %}
+{{indent}}
+{%- endfor -%}
+{%- endmacro doc -%}
 
-pub static PERIPHERALS_TEMPLATE: &'static str = "peripherals.rs";
-static PERIPHERALS_TEMPLATE_CONTENT: &'static str = "
-{%- import \"reg_def_macros.rs\" as macros -%}
-#![allow(non_snake_case)]
-{%- for peripheral in peripherals -%}
-{%-if peripheral.content%}
-mod {{peripheral.content.name}}{
-{%- for register in peripheral.content.registers %}
-    create_reg! { {{peripheral.content.name}}::{{register.name}}(u32) =>
-        {%-if register.readWriteFields%}
+{#- ptr() of a peripheral module, the register macros reach the register block through it -#}
+{%- macro blockPointer(name, baseAddress, access) -%}
+{%- if access == \"pointers\" -%}
+/// Address of the register block
+    pub const BASE_ADDRESS: usize = {{baseAddress | hex}};
+    /// Pointer to the register block, registers are only accessed through volatile reads and writes
+    pub const fn ptr() -> *mut RegisterBlock {
+        BASE_ADDRESS as *mut RegisterBlock
+    }
+{%- else -%}
+/// Pointer to the register block, the linker places it through peripheral.x
+    #[cfg(not(feature = \"host-sim\"))]
+    #[allow(unused_unsafe)]
+    pub fn ptr() -> *mut RegisterBlock {
+        unsafe { core::ptr::addr_of_mut!(super::{{name}}) }
+    }
+    /// Pointer to the register block, its registers are simulated
+    #[cfg(feature = \"host-sim\")]
+    pub fn ptr() -> *mut RegisterBlock {
+        {{baseAddress | hex}} as *mut RegisterBlock
+    }
+{%- endif -%}
+{%- endmacro blockPointer -%}
+
+{#- Ownership token of a peripheral or entity module, see Peripherals::take -#}
+{%- macro token(owner) -%}
+/// Ownership of the {{owner}}, its functions take `&Token` to read and `&mut Token` to write.
+    /// `crate::Peripherals::take` hands out the only one.
+    pub struct Token(());
+    impl Token {
+        /// Creates another token.
+        /// The caller has to make sure that the {{owner}} is not accessed through two tokens at the same time.
+        pub unsafe fn steal() -> Token {
+            Token(())
+        }
+    }
+{%- endmacro token -%}
+
+{%- macro fields(fields, nameKey, prefix=\"\") -%}
+{%- for field in fields %}
+            {{ self::doc(lines=field.doc, indent=\"            \") }}{{prefix}}{{field[nameKey]}}({{field.mask | hex}}, {{ self::fieldType(fieldType=field.fieldType) }}){% if field.range %}[{{field.range.0 | hex}}, {{field.range.1 | hex}}]{% endif %},
+{%- endfor %}
+{%- endmacro fields -%}
+
+{%- macro createReg(peripheralName, register, nameKey=\"name\", token=false) -%}
+{#- Fields with readAction only get pop_* getters, their setters are listed as write-only -#}
+{%- set_global rw_fields = [] -%}
+{%- set_global r_fields = [] -%}
+{%- set_global w_fields = register.writeFields -%}
+{%- set_global pop_fields = [] -%}
+{%- for field in register.readWriteFields -%}
+    {%- if field.readAction -%}
+        {%- set_global w_fields = w_fields | concat(with=field) -%}
+        {%- set_global pop_fields = pop_fields | concat(with=field) -%}
+    {%- else -%}
+        {%- set_global rw_fields = rw_fields | concat(with=field) -%}
+    {%- endif -%}
+{%- endfor -%}
+{%- for field in register.readFields -%}
+    {%- if field.readAction -%}
+        {%- set_global pop_fields = pop_fields | concat(with=field) -%}
+    {%- else -%}
+        {%- set_global r_fields = r_fields | concat(with=field) -%}
+    {%- endif -%}
+{%- endfor -%}
+create_reg! {
+        {{ self::doc(lines=register.doc, indent=\"        \") }}{{peripheralName}}::{{register.name}}
+        {%- if register.dim %}[{{register.elements.0.name}}; {{register.dim}}; {{register.dimIncrement | hex}}]{% endif -%}
+        (u{{register.size}}) @ {{register.address | hex}}{% if token %} in Token{% endif %} =>
+        {%-if rw_fields%}
         RW{
-            {% for field in register.readWriteFields -%}
-            {{field.name}}({{field.mask | hex}}, {{ macros::fieldType(fieldType=field.fieldType) }}),
-            {%- endfor %}
+            {{- self::fields(fields=rw_fields, nameKey=nameKey) }}
         }
         {%-endif-%}
-        {%-if register.readFields%}
+        {%-if r_fields%}
         R{
-            {% for field in register.readFields -%}
-            {{field.name}}({{field.mask | hex}}, {{ macros::fieldType(fieldType=field.fieldType) }}),
-            {%- endfor %}
+            {{- self::fields(fields=r_fields, nameKey=nameKey) }}
         }
         {%-endif-%}
-        {%-if register.writeFields%}
+        {%-if w_fields%}
         W{
-            {% for field in register.writeFields -%}
-            {{field.name}}({{field.mask | hex}}, {{ macros::fieldType(fieldType=field.fieldType) }}),
+            {{- self::fields(fields=w_fields, nameKey=nameKey) }}
+        }
+        {%-endif-%}
+        {%-if pop_fields%}
+        POP{
+            {{- self::fields(fields=pop_fields, nameKey=nameKey, prefix=\"pop_\") }}
+        }
+        {%-endif-%}
+        {%-set clear_fields = register.readWriteFields | concat(with=register.writeFields) | filter(attribute=\"clear\") %}
+        {%-if clear_fields%}
+        CLEAR{
+            {%- for field in clear_fields %}
+            /// Clears `{{field[nameKey]}}` by writing {{field.clear}} into it
+            clear_{{field[nameKey]}}({{field.mask | hex}}, {{field.clear}}),
+            {%- endfor %}
+        }
+        {%-endif-%}
+        {%-if register.writeZerosMask%}
+        WRITE_ZEROS({{register.writeZerosMask | hex}})
+        {%-endif-%}
+        {%-if register.writeOnesMask%}
+        WRITE_ONES({{register.writeOnesMask | hex}})
+        {%-endif-%}
+        {%-if register.readAction%}
+        READ_ACTION(RESET_VALUE)
+        {%-endif-%}
+        {%-if register.resetValue is defined-%}{%-if register.resetValue is number%}
+        RESET({{register.resetValue | hex}}, {{register.resetMask | hex}})
+        {%-endif-%}{%-endif-%}
+        {%-if register.atomic%}
+        {%-if register.atomic.policy == \"critical_section\"%}
+        ATOMIC(CRITICAL_SECTION)
+        {%-elif register.atomic.policy == \"bit_band\"%}
+        ATOMIC(BIT_BAND)
+        {%-else%}
+        ATOMIC(SET_CLEAR({{register.atomic.setOffset}}, {{register.atomic.clearOffset}}, {{register.atomic.clearShift}}))
+        {%-endif-%}
+        {%-endif-%}
+        {%-set all_fields = register.readWriteFields | concat(with=register.readFields) | concat(with=register.writeFields) -%}
+        {%-if all_fields%}
+        FIELDS{
+            {%- for field in all_fields %}
+            {{ self::doc(lines=field.doc, indent=\"            \") }}{{field[nameKey]}}({{field.mask | hex}}),
             {%- endfor %}
         }
         {%-endif%}
     }
+{%- endmacro createReg -%}";
+
+pub static SRC_LIB_RS_TEMPLATE: &str = "src_lib.rs";
+static SRC_LIB_RS_TEMPLATE_CONTENT: &str = "\
+#![no_std]
+
+#[macro_use]
+mod macros;
+mod access;
+mod hal;
+mod interrupts;
+pub mod peripherals;
+#[cfg(feature = \"host-sim\")]
+pub mod sim;
+#[cfg(feature = \"trace\")]
+pub mod trace;
+
+pub use hal::*;
+pub use interrupts::Interrupt;
+/// Names the handlers of the `#[interrupt]` attribute of cortex-m-rt
+pub use interrupts::Interrupt as interrupt;
+#[cfg(feature = \"rt\")]
+pub use cortex_m_rt::interrupt;
+{%- if cpu %}
+
+/// Core of the device
+pub const CPU: &str = \"{{cpu.name}}\";
+/// Revision of the core, rNpM
+pub const CPU_REVISION: &str = \"{{cpu.revision}}\";
+/// Endianness of the core: little, big, selectable or other
+pub const CPU_ENDIAN: &str = \"{{cpu.endian}}\";
+/// Number of priority bits implemented by the NVIC
+pub const NVIC_PRIO_BITS: u8 = {{cpu.nvicPrioBits}};
+/// The core has a memory protection unit
+pub const MPU_PRESENT: bool = {{cpu.mpuPresent}};
+/// The core has a floating point unit
+pub const FPU_PRESENT: bool = {{cpu.fpuPresent}};
+/// The vector table offset register is implemented
+pub const VTOR_PRESENT: bool = {{cpu.vtorPresent}};
+{%- endif %}
+{%- if peripheralTokens %}
+
+/// Ownership tokens of all peripherals and entities, the register functions need them
+#[allow(non_snake_case)]
+pub struct Peripherals {
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}{% set name = peripheral.content.name %}{% else %}{% set name = peripheral.derived.name %}{% endif %}
+    /// Token of {{name}}
+    pub {{name}}: peripherals::{{name}}::Token,
+{%- endfor %}
+{%- for entity in halEntities %}
+    /// Token of the entity {{entity.name}}
+    pub {{entity.name | snake}}: {{entity.name | snake}}::Token,
+{%- endfor %}
+}
+
+#[cfg(not(feature = \"host-sim\"))]
+static mut TAKEN: bool = false;
+
+impl Peripherals {
+    /// Hands out the tokens once, `None` if they were taken before
+    pub fn take() -> Option<Peripherals> {
+        if Peripherals::swap_taken() {
+            None
+        } else {
+            Some(unsafe { Peripherals::tokens() })
+        }
+    }
+    /// Hands out the tokens even if they were taken before.
+    /// The caller has to make sure that no peripheral is accessed through two tokens at the same time.
+    pub unsafe fn steal() -> Peripherals {
+        Peripherals::swap_taken();
+        Peripherals::tokens()
+    }
+    /// Marks the tokens as taken, true if they were taken before
+    #[cfg(not(feature = \"host-sim\"))]
+    fn swap_taken() -> bool {
+        cortex_m::interrupt::free(|_| unsafe {
+            let taken = TAKEN;
+            TAKEN = true;
+            taken
+        })
+    }
+    /// Marks the tokens as taken, true if they were taken before. Every thread simulates its own device.
+    #[cfg(feature = \"host-sim\")]
+    fn swap_taken() -> bool {
+        sim::swap_taken()
+    }
+    unsafe fn tokens() -> Peripherals {
+        Peripherals {
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}{% set name = peripheral.content.name %}{% else %}{% set name = peripheral.derived.name %}{% endif %}
+            {{name}}: peripherals::{{name}}::Token::steal(),
+{%- endfor %}
+{%- for entity in halEntities %}
+            {{entity.name | snake}}: {{entity.name | snake}}::Token::steal(),
+{%- endfor %}
+        }
+    }
+}
+{%- endif %}
+";
+
+pub static PERIPHERALS_TEMPLATE: &str = "peripherals.rs";
+static PERIPHERALS_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros -%}
+#![allow(non_snake_case)]
+#![allow(non_camel_case_types)]
+{% include \"enumerated_types.rs\" %}
+{%- for peripheral in peripherals -%}
+{%-if peripheral.content%}
+{%- set content = peripheral.content %}
+{%- include \"peripheral_content.rs\" %}
+{{ macros::doc(lines=content.doc) }}pub mod {{peripheral.content.name}}{
+    /// Register block of the peripheral
+    pub type RegisterBlock = super::{{peripheral.content.name}}_struct;
+    {{ macros::blockPointer(name=peripheral.content.name, baseAddress=peripheral.content.baseAddress, access=peripheralAccess) }}
+{%- if peripheralTokens %}
+    {{ macros::token(owner=\"peripheral\") }}
+{%- endif %}
+{%- for register in peripheral.content.registers %}
+    {{ macros::createReg(peripheralName=peripheral.content.name, register=register, token=peripheralTokens) }}
 {%- endfor %}
 }
 {%-elif peripheral.derived%}
-//Derived peripherals not supported yet
+{%- set link = peripheral.derived %}
+{%- if link.overrides %}
+{%- set content = link %}
+{%- include \"peripheral_content.rs\" %}
+{{ macros::doc(lines=link.doc) }}pub mod {{link.name}}{
+    /// Register block of the peripheral
+    pub type RegisterBlock = super::{{link.name}}_struct;
+    {{ macros::blockPointer(name=link.name, baseAddress=link.baseAddress, access=peripheralAccess) }}
+{%- if peripheralTokens %}
+    {{ macros::token(owner=\"peripheral\") }}
+{%- endif %}
+{%- for register in link.registers %}
+    {{ macros::createReg(peripheralName=link.name, register=register, token=peripheralTokens) }}
+{%- endfor %}
+}
+{%- else %}
+{%- include \"peripheral_link.rs\" %}
+{{ macros::doc(lines=link.doc) }}pub mod {{link.name}}{
+    /// Register block of the peripheral, shared with {{link.derivedFrom}}
+    pub type RegisterBlock = super::{{link.derivedFrom}}_struct;
+    {{ macros::blockPointer(name=link.name, baseAddress=link.baseAddress, access=peripheralAccess) }}
+{%- if peripheralTokens %}
+    {{ macros::token(owner=\"peripheral\") }}
+{%- endif %}
+{%- for register in link.registers %}
+    create_derived_reg! {
+        {{ macros::doc(lines=register.doc, indent=\"        \") }}{{link.name}}::{{register.name}}{% if register.dim %}[]{% endif %} @ {{register.address | hex}}{% if peripheralTokens %} in Token{% endif %} => {{link.derivedFrom}}
+    }
+{%- endfor %}
+}
+{%- endif %}
 {%-endif-%}
 {%-endfor%}";
 
-pub static PERIPHERAL_CONTENT_TEMPLATE: &'static str = "peripheral_content.rs";
-static PERIPHERAL_CONTENT_TEMPLATE_CONTENT: &'static str = "\
-extern \"C\" \\{
-    #[no_mangle]
-    pub static mut {content.name}: {content.name}_struct;
+pub static PERIPHERAL_CONTENT_TEMPLATE: &str = "peripheral_content.rs";
+static PERIPHERAL_CONTENT_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros %}
+{{ macros::doc(lines=content.doc) }}#[repr(C)]
+pub struct {{content.name}}_struct {
+{%- for item in content.registerBlock.items %}
+{%- if item.reserved %}
+    {{item.name}}: [u8; {{item.size | hex}}],
+{%- else %}
+    {{ macros::doc(lines=item.doc, indent=\"    \") }}pub {{item.name}}: u{{item.size * 8}},
+{%- endif %}
+{%- endfor %}
 }
-#[repr(C)]
-#[no_mangle]
-pub struct {content.name}_struct \\{\
-    {{ for register in content.registers }}
-    pub {register.name}: u{register.size},\
-    {{ endfor }}
+const _: () = {
+{%- for item in content.registerBlock.items %}
+{%- if not item.reserved %}
+    assert!(core::mem::offset_of!({{content.name}}_struct, {{item.name}}) == {{item.offset | hex}});
+{%- endif %}
+{%- endfor %}
+    assert!(core::mem::size_of::<{{content.name}}_struct>() == {{content.registerBlock.size | hex}});
+};
+{%- if peripheralAccess == \"symbols\" %}
+extern \"C\" {
+    /// Register block at {{content.baseAddress | hex}}, placed by peripheral.x
+    #[link_name = \"{{content.linkName}}\"]
+    pub static mut {{content.name}}: {{content.name}}_struct;
 }
-";
+{%- endif %}";
 
-pub static PERIPHERAL_LINK_TEMPLATE: &'static str = "peripheral_link.rs";
-static PERIPHERAL_LINK_TEMPLATE_CONTENT: &'static str = "\
-#[allow(non_snake_case)]
-extern \"C\" \\{
-    #[no_mangle]
-    pub static mut {link.name}: {link.derivedFrom}_struct;
+pub static PERIPHERAL_LINK_TEMPLATE: &str = "peripheral_link.rs";
+static PERIPHERAL_LINK_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros %}
+{%- if peripheralAccess == \"symbols\" %}
+extern \"C\" {
+    /// Register block at {{link.baseAddress | hex}}, placed by peripheral.x
+    #[link_name = \"{{link.linkName}}\"]
+    pub static mut {{link.name}}: {{link.derivedFrom}}_struct;
 }
-";
-
-pub static PERIPHERAL_MOD_TEMPLATE: &'static str = "peripheral_mod.rs";
-static PERIPHERAL_MOD_TEMPLATE_CONTENT: &'static str = "\
-#![allow(non_camel_case_types)]
-#![allow(non_snake_case)]
+{%- endif %}";
 
-#[macro_use]
-pub mod macros;
-pub mod types;
-";
-
-pub static LINKER_TEMPLATE: &'static str = "peripheral.x";
-static LINKER_TEMPLATE_CONTENT: &'static str = "\
+pub static LINKER_TEMPLATE: &str = "peripheral.x";
+static LINKER_TEMPLATE_CONTENT: &str = "\
+/* Register blocks of the peripherals, each symbol is the name of its peripheral.
+{%- set_global renamed = [] %}
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}{% set p = peripheral.content %}{% else %}{% set p = peripheral.derived %}{% endif %}
+{%- if p.linkName != p.name %}{% set_global renamed = renamed | concat(with=p.name) %}{% endif %}
+{%- endfor %}
+{%- if renamed %}
+   Peripherals that share their name with an interrupt handler use __peripheral_<name> instead:
+   {{renamed | join(sep=\", \")}} */
+{%- else %} */
+{%- endif %}
 SECTIONS { \
 {% for peripheral in peripherals %}
-    {% if peripheral.content %}{{peripheral.content.name}} = {{peripheral.content.baseAddress}};\
-    {% elif peripheral.derived %}{{peripheral.derived.name}} = {{peripheral.derived.baseAddress}};\
+    {% if peripheral.content %}{{peripheral.content.linkName}} = {{peripheral.content.baseAddress}};\
+    {% elif peripheral.derived %}{{peripheral.derived.linkName}} = {{peripheral.derived.baseAddress}};\
     {%endif%}\
 {% endfor %}
 }";
 
-pub static HAL_MOD_RS_TEMPLATE: &'static str = "hal_mod.rs";
-static HAL_MOD_RS_TEMPLATE_CONTENT: &'static str = "\
-{{ for definition in definitions }}\
-pub mod {definition.name};
-{{ endfor }}";
+pub static BUILD_RS_TEMPLATE: &str = "build.rs";
+static BUILD_RS_TEMPLATE_CONTENT: &str = "\
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    let out = &PathBuf::from(env::var_os(\"OUT_DIR\").unwrap());
+{%- if peripheralAccess == \"symbols\" %}
+    // Put peripheral.x in the linker search path so that the firmware can link it
+    File::create(out.join(\"peripheral.x\"))
+        .unwrap()
+        .write_all(include_bytes!(\"peripheral.x\"))
+        .unwrap();
+{%- endif %}
+    // cortex-m-rt includes device.x with the default handlers of the interrupts
+    if env::var_os(\"CARGO_FEATURE_RT\").is_some() {
+        File::create(out.join(\"device.x\"))
+            .unwrap()
+            .write_all(include_bytes!(\"device.x\"))
+            .unwrap();
+    }
+    println!(\"cargo:rustc-link-search={}\", out.display());
+    println!(\"cargo:rerun-if-changed=build.rs\");
+{%- if peripheralAccess == \"symbols\" %}
+    println!(\"cargo:rerun-if-changed=peripheral.x\");
+{%- endif %}
+    println!(\"cargo:rerun-if-changed=device.x\");
+}
+";
+
+pub static DEVICE_X_TEMPLATE: &str = "device.x";
+static DEVICE_X_TEMPLATE_CONTENT: &str = "\
+{%- for interrupt in interrupts %}
+PROVIDE({{interrupt.name}} = DefaultHandler);
+{%- endfor %}
+";
+
+pub static ACCESS_TEMPLATE: &str = "access.rs";
+static ACCESS_TEMPLATE_CONTENT: &str = "\
+//! Volatile accesses of the registers, the `host-sim` feature routes them to the simulated
+//! registers and the `trace` feature reports them
+
+/// Register sizes, the simulated registers and the tracer see all of them as u64
+#[cfg_attr(not(any(feature = \"host-sim\", feature = \"trace\")), allow(dead_code))]
+pub trait Bits: Copy {
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_bits {
+    ($($type:ident),*) => {
+        $(
+        impl Bits for $type {
+            fn to_u64(self) -> u64 {
+                self as u64
+            }
+            fn from_u64(value: u64) -> Self {
+                value as $type
+            }
+        }
+        )*
+    };
+}
+impl_bits!(u8, u16, u32, u64);
+
+/// Reads the register at `address`.
+/// The caller has to make sure that `address` points to such a register.
+#[inline(always)]
+pub unsafe fn read<T: Bits>(address: *const T) -> T {
+    #[cfg(not(feature = \"host-sim\"))]
+    let value = core::ptr::read_volatile(address);
+    #[cfg(feature = \"host-sim\")]
+    let value = T::from_u64(crate::sim::read(address as usize));
+    #[cfg(feature = \"trace\")]
+    crate::trace::report(
+        crate::trace::Kind::Read,
+        address as usize,
+        value.to_u64(),
+        T::from_u64(!0).to_u64(),
+    );
+    value
+}
+
+/// Writes `value` into the register at `address`, `mask` are the bits the caller set explicitly.
+/// The caller has to make sure that `address` points to such a register.
+#[inline(always)]
+#[allow(unused_variables)]
+pub unsafe fn write<T: Bits>(address: *mut T, value: T, mask: T) {
+    #[cfg(feature = \"trace\")]
+    crate::trace::report(crate::trace::Kind::Write, address as usize, value.to_u64(), mask.to_u64());
+    #[cfg(not(feature = \"host-sim\"))]
+    core::ptr::write_volatile(address, value);
+    #[cfg(feature = \"host-sim\")]
+    crate::sim::write(address as usize, value.to_u64());
+}
+
+/// Register and bit that a write to the Cortex-M3/M4 bit-band alias `address` changes
+#[cfg(any(feature = \"host-sim\", feature = \"trace\"))]
+pub fn bit_band_target(address: usize) -> Option<(usize, u32)> {
+    let region = address & 0xF000_0000;
+    if (region != 0x2000_0000 && region != 0x4000_0000) || address & 0x0E00_0000 != 0x0200_0000 {
+        return None;
+    }
+    let offset = address & 0x01FF_FFFF;
+    let byte = region + offset / 32;
+    let bit = (offset % 32) as u32 / 4;
+    // The alias names a bit of a byte, which belongs to the register that contains it
+    let register = register_at(byte).map_or(byte & !3, |(register, _)| register);
+    Some((register, bit + (byte - register) as u32 * 8))
+}
+
+/// Address and size in bytes of the register that contains the byte at `address`
+#[cfg(any(feature = \"host-sim\", feature = \"trace\"))]
+pub fn register_at(address: usize) -> Option<(usize, usize)> {
+    let index = REGISTER_SIZES.partition_point(|(start, _)| *start <= address);
+    let &(start, size) = REGISTER_SIZES[..index].last()?;
+    if address < start + size / 8 {
+        Some((start, size / 8))
+    } else {
+        None
+    }
+}
+
+/// Address and size in bits of all registers, sorted by address
+#[cfg(any(feature = \"host-sim\", feature = \"trace\"))]
+static REGISTER_SIZES: [(usize, usize); {{registerMap | length}}] = [
+{%- for register in registerMap %}
+    ({{register.address | hex}}, {{register.size}}),
+{%- endfor %}
+];
+
+/// Peripheral and name of the register at `address`
+#[cfg(feature = \"trace\")]
+pub fn register_name(address: usize) -> Option<(&'static str, &'static str)> {
+    REGISTER_NAMES
+        .binary_search_by_key(&address, |(address, ..)| *address)
+        .ok()
+        .map(|index| (REGISTER_NAMES[index].1, REGISTER_NAMES[index].2))
+}
+
+/// Peripheral and name of all registers, sorted by address
+#[cfg(feature = \"trace\")]
+static REGISTER_NAMES: [(usize, &str, &str); {{registerMap | length}}] = [
+{%- for register in registerMap %}
+    ({{register.address | hex}}, \"{{register.peripheral}}\", \"{{register.name}}\"),
+{%- endfor %}
+];
+";
+
+pub static SIM_TEMPLATE: &str = "sim.rs";
+static SIM_TEMPLATE_CONTENT: &str = "\
+//! Simulated registers for testing drivers on the host, enabled by the `host-sim` feature.
+//!
+//! Every thread has its own register file, so tests that run in parallel do not see each other.
+//! The registers start with their reset value, hooks simulate the responses of the hardware.
+extern crate std;
+
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Reset values of all registers, sorted by address
+static RESET_VALUES: [(usize, u64); {{registerMap | length}}] = [
+{%- for register in registerMap %}
+    ({{register.address | hex}}, {{register.resetValue | hex}}),
+{%- endfor %}
+];
+
+/// Computes the value a read returns from the value of the register
+pub type ReadHook = Box<dyn FnMut(u64) -> u64>;
+/// Computes the new value of the register from its current value and the value written
+pub type WriteHook = Box<dyn FnMut(u64, u64) -> u64>;
+
+#[derive(Default)]
+struct Device {
+    values: BTreeMap<usize, u64>,
+    read_hooks: BTreeMap<usize, ReadHook>,
+    write_hooks: BTreeMap<usize, WriteHook>,
+{%- if peripheralTokens %}
+    taken: bool,
+{%- endif %}
+}
+
+std::thread_local! {
+    static DEVICE: RefCell<Device> = RefCell::new(Device::default());
+}
 
-pub static HAL_TEMPLATE: &'static str = "hal.rs";
-static HAL_TEMPLATE_CONTENT: &'static str = "\
+/// Puts all registers back to their reset value and removes all hooks
+pub fn reset() {
+    DEVICE.with(|device| *device.borrow_mut() = Device::default());
+}
+
+/// Reset value of the register at `address`, zero if it has none
+pub fn reset_value(address: usize) -> u64 {
+    RESET_VALUES
+        .binary_search_by_key(&address, |(address, _)| *address)
+        .map_or(0, |index| RESET_VALUES[index].1)
+}
+
+/// Value of the register at `address`, without running its hooks
+pub fn peek(address: usize) -> u64 {
+    DEVICE.with(|device| device.borrow().values.get(&address).copied())
+        .unwrap_or_else(|| reset_value(address))
+}
+
+/// Sets the register at `address` without running its hooks, e.g. to raise a flag
+pub fn poke(address: usize, value: u64) {
+    DEVICE.with(|device| device.borrow_mut().values.insert(address, value));
+}
+
+/// Simulates reads of the register at `address`: `hook` gets the value of the register and returns
+/// the value the read returns. It may call `poke`, e.g. to clear flags on read or to pop a FIFO.
+pub fn on_read(address: usize, hook: impl FnMut(u64) -> u64 + 'static) {
+    DEVICE.with(|device| device.borrow_mut().read_hooks.insert(address, Box::new(hook)));
+}
+
+/// Simulates writes of the register at `address`: `hook` gets the value of the register and the
+/// value written and returns the new value, e.g. with a ready flag set or write-1-to-clear flags cleared.
+pub fn on_write(address: usize, hook: impl FnMut(u64, u64) -> u64 + 'static) {
+    DEVICE.with(|device| device.borrow_mut().write_hooks.insert(address, Box::new(hook)));
+}
+
+pub(crate) fn read(address: usize) -> u64 {
+    let value = peek(address);
+    // The hook is taken out while it runs, so that it can use the other functions
+    match DEVICE.with(|device| device.borrow_mut().read_hooks.remove(&address)) {
+        Some(mut hook) => {
+            let value = hook(value);
+            DEVICE.with(|device| {
+                device.borrow_mut().read_hooks.entry(address).or_insert(hook);
+            });
+            value
+        }
+        None => value,
+    }
+}
+
+pub(crate) fn write(address: usize, value: u64) {
+    let (address, value) = match crate::access::bit_band_target(address) {
+        Some((address, bit)) => {
+            let current = peek(address);
+            (address, (current & !(1 << bit)) | ((value & 1) << bit))
+        }
+        None => (address, value),
+    };
+    // The register keeps only the bits it has, also of the value its hook returns
+    let bits = crate::access::register_at(address).map_or(u64::MAX, |(_, size)| u64::MAX >> (64 - 8 * size));
+    let value = value & bits;
+    let value = match DEVICE.with(|device| device.borrow_mut().write_hooks.remove(&address)) {
+        Some(mut hook) => {
+            let value = hook(peek(address), value);
+            DEVICE.with(|device| {
+                device.borrow_mut().write_hooks.entry(address).or_insert(hook);
+            });
+            value
+        }
+        None => value,
+    };
+    poke(address, value & bits);
+}
+
+{%- if peripheralTokens %}
+
+pub(crate) fn swap_taken() -> bool {
+    DEVICE.with(|device| core::mem::replace(&mut device.borrow_mut().taken, true))
+}
+{%- endif %}
+";
+
+pub static TRACE_TEMPLATE: &str = "trace.rs";
+static TRACE_TEMPLATE_CONTENT: &str = "\
+//! Reports every register access to a function set by the user, enabled by the `trace` feature.
+//! The tracer is global, tests that run in parallel see the accesses of each other.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Direction of a register access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Read,
+    Write,
+}
+
+/// A register access as seen by the tracer
+#[derive(Clone, Copy, Debug)]
+pub struct Access {
+    pub kind: Kind,
+    /// Peripheral of the register, empty if the address is not a register of the device
+    pub peripheral: &'static str,
+    /// Name of the register, e.g. CCR2 for an element of a register array
+    pub register: &'static str,
+    /// Address of the access, the alias address for bit-band writes
+    pub address: usize,
+    pub value: u64,
+    /// Bits the access is about: all bits of the register for reads, the bits that were set
+    /// explicitly for writes
+    pub mask: u64,
+}
+
+/// The tracer as usize, zero if there is none
+static TRACER: AtomicUsize = AtomicUsize::new(0);
+
+/// Calls `tracer` for every register access from now on, e.g. to log it over RTT or a UART.
+/// The tracer must not access registers itself.
+pub fn set_tracer(tracer: fn(&Access)) {
+    TRACER.store(tracer as usize, Ordering::Release);
+}
+
+/// Stops reporting register accesses
+pub fn clear_tracer() {
+    TRACER.store(0, Ordering::Release);
+}
+
+pub(crate) fn report(kind: Kind, address: usize, value: u64, mask: u64) {
+    let tracer = TRACER.load(Ordering::Acquire);
+    if tracer == 0 {
+        return;
+    }
+    let tracer: fn(&Access) = unsafe { core::mem::transmute(tracer) };
+    // Bit-band writes are reported with the register and bit they change
+    let (register_address, value, mask) = match crate::access::bit_band_target(address) {
+        Some((register_address, bit)) => (register_address, value << bit, mask << bit),
+        None => (address, value, mask),
+    };
+    let (peripheral, register) = crate::access::register_name(register_address).unwrap_or((\"\", \"\"));
+    tracer(&Access {
+        kind,
+        peripheral,
+        register,
+        address,
+        value,
+        mask,
+    });
+}
+";
+
+pub static C_HEADER_TEMPLATE: &str = "device.h";
+static C_HEADER_TEMPLATE_CONTENT: &str = "\
+/* {{name}} peripheral access header, generated by svd2hal from the patched svd. Do not edit. */
+#ifndef {{name | upper}}_H
+#define {{name | upper}}_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+
+{%- if interrupts %}
+
+/* Interrupt numbers */
+typedef enum {
+{%- for interrupt in interrupts %}
+    {{interrupt.name}}_IRQn = {{interrupt.value}},
+{%- endfor %}
+} IRQn_Type;
+{%- endif %}
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}
+{%- set content = peripheral.content %}
+{%- include \"device.h.block\" %}
+{%- elif peripheral.derived.overrides %}
+{%- set content = peripheral.derived %}
+{%- include \"device.h.block\" %}
+{%- else %}
+{%- set link = peripheral.derived %}
+
+/* {{link.name}}, derived from {{link.derivedFrom}} */
+#define {{link.name}}_BASE {{link.baseAddress | hex}}UL
+#define {{link.name}} (({{link.derivedFrom}}_TypeDef *) {{link.name}}_BASE)
+{%- endif %}
+{%- endfor %}
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* {{name | upper}}_H */
+";
+
+pub static C_HEADER_BLOCK_TEMPLATE: &str = "device.h.block";
+static C_HEADER_BLOCK_TEMPLATE_CONTENT: &str = "
+
+/* {{content.name}}{% if content.description %}: {{content.description | replace(from=\"*/\", to=\"* /\")}}{% endif %} */
+typedef struct {
+{%- for item in content.registerBlock.items %}
+{%- if item.reserved %}
+    uint8_t {{item.name}}[{{item.size | hex}}];
+{%- else %}
+    volatile uint{{item.size * 8}}_t {{item.name}}; /* Offset {{item.offset | hex}} */
+{%- endif %}
+{%- endfor %}
+} {{content.name}}_TypeDef;
+
+#define {{content.name}}_BASE {{content.baseAddress | hex}}UL
+#define {{content.name}} (({{content.name}}_TypeDef *) {{content.name}}_BASE)
+{%- for register in content.registers %}
+{%- set prefix = content.name ~ \"_\" ~ register.name %}
+{%- for field in register.readWriteFields | concat(with=register.readFields) | concat(with=register.writeFields) %}
+#define {{prefix}}_{{field.name}}_Pos ({{field.bitOffset}}U)
+#define {{prefix}}_{{field.name}}_Msk ({{field.mask | hex}}U{% if register.size > 32 %}LL{% else %}L{% endif %})
+{%- endfor %}
+{%- endfor %}";
+
+pub static REFERENCE_TEMPLATE: &str = "registers.md";
+static REFERENCE_TEMPLATE_CONTENT: &str = "\
+# {{name}} register reference
+
+Generated by svd2hal from the patched svd, it can differ from the reference manual of the vendor.
+
+| Peripheral | Base address | Description |
+|---|---|---|
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}{% set p = peripheral.content %}{% else %}{% set p = peripheral.derived %}{% endif %}
+| [{{p.name}}](#{{p.name | lower}}) | `{{p.baseAddress | hex}}` | {% if p.derivedFrom %}Derived from {{p.derivedFrom}}{% else %}{{p.brief}}{% endif %} |
+{%- endfor %}
+{%- for peripheral in peripherals %}
+{%- if peripheral.content %}{% set p = peripheral.content %}{% else %}{% set p = peripheral.derived %}{% endif %}
+
+## {{p.name}}
+
+{% if p.brief %}{{p.brief}}
+
+{% endif %}Base address: `{{p.baseAddress | hex}}`{% if p.derivedFrom %}, derived from [{{p.derivedFrom}}](#{{p.derivedFrom | lower}}){% endif %}
+{%- if not p.derivedFrom or p.overrides %}
+
+| Register | Offset | Size | Access | Reset value | Description |
+|---|---|---|---|---|---|
+{%- for register in p.registers %}
+| {{register.name}}{% if register.dim %}\\[{{register.dim}}\\]{% endif %} | `{{register.elements.0.addressOffset | hex}}` | {{register.size}} | {{register.access | default(value=\"\")}} | {% if register.resetValue is defined %}{% if register.resetValue is number %}`{{register.resetValue | hex}}`{% endif %}{% endif %} | {{register.brief}} |
+{%- endfor %}
+{%- for register in p.registers %}
+{%- include \"registers.md.register\" %}
+{%- endfor %}
+{%- endif %}
+{%- endfor %}
+";
+
+pub static REFERENCE_REGISTER_TEMPLATE: &str = "registers.md.register";
+static REFERENCE_REGISTER_TEMPLATE_CONTENT: &str = "
+
+### {{p.name}}.{{register.name}}
+
+{% if register.brief %}{{register.brief}}
+
+{% endif %}Address: `{{register.address | hex}}`{% if register.dim %}, {{register.dim}} elements every {{register.dimIncrement}} bytes{% endif %}
+
+<table>
+<tr>{% for bit_field in register.bitFields %}<td colspan=\"{{bit_field.width}}\" align=\"center\">{{bit_field.msb}}{% if bit_field.width > 1 %}:{{bit_field.lsb}}{% endif %}</td>{% endfor %}</tr>
+<tr>{% for bit_field in register.bitFields %}<td colspan=\"{{bit_field.width}}\" align=\"center\">{% if bit_field.reserved %}<i>reserved</i>{% else %}{{bit_field.name}}{% endif %}</td>{% endfor %}</tr>
+</table>
+{%- set fields = register.bitFields | filter(attribute=\"reserved\", value=false) %}
+{%- if fields %}
+
+| Bits | Field | Access | Reset value | Description |
+|---|---|---|---|---|
+{%- for field in fields %}
+| {{field.msb}}{% if field.width > 1 %}:{{field.lsb}}{% endif %} | {{field.name}} | {{field.access | default(value=\"\")}} | {% if field.resetValue is number %}`{{field.resetValue | hex}}`{% endif %} | {{field.brief}}
+{%- for value in field.enumValues %}<br>`{{value.value | hex}}` {{value.name}}{% if value.brief %}: {{value.brief}}{% endif %}{% endfor %} |
+{%- endfor %}
+{%- endif %}";
+
+pub static INTERRUPTS_TEMPLATE: &str = "interrupts.rs";
+static INTERRUPTS_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros -%}
+/// Interrupts of the device, can be passed to the NVIC functions of cortex-m
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+{%- if interrupts %}
+#[repr(u16)]
+{%- endif %}
+#[allow(non_camel_case_types)]
+pub enum Interrupt {
+{%- for interrupt in interrupts %}
+    {{ macros::doc(lines=interrupt.doc, indent=\"    \") }}{{interrupt.name}} = {{interrupt.value}},
+{%- endfor %}
+}
+
+unsafe impl cortex_m::interrupt::InterruptNumber for Interrupt {
+    fn number(self) -> u16 {
+        self as u16
+    }
+}
+
+#[cfg(feature = \"rt\")]
+extern \"C\" {
+{%- for interrupt in interrupts %}
+    fn {{interrupt.name}}();
+{%- endfor %}
+}
+
+/// Entry of the interrupt vector table
+#[cfg(feature = \"rt\")]
+#[doc(hidden)]
+pub union Vector {
+    _handler: unsafe extern \"C\" fn(),
+    _reserved: u32,
+}
+
+/// Interrupt vector table, placed after the exceptions by cortex-m-rt
+#[cfg(feature = \"rt\")]
+#[doc(hidden)]
+#[link_section = \".vector_table.interrupts\"]
+#[no_mangle]
+pub static __INTERRUPTS: [Vector; {{vectors | length}}] = [
+{%- for vector in vectors %}
+{%- if vector %}
+    Vector { _handler: {{vector}} },
+{%- else %}
+    Vector { _reserved: 0 },
+{%- endif %}
+{%- endfor %}
+];
+";
+
+pub static HAL_TEMPLATE: &str = "hal.rs";
+static HAL_TEMPLATE_CONTENT: &str = "\
+{%- import \"reg_def_macros.rs\" as macros -%}
 #![allow(dead_code)]
-{{ for entity in halEntities }}
-pub mod {entity.name | snake} \\{
-{{call hal_mod.rs.entity_types with entity}}
-{{call hal_mod.rs.entity_regs with entity}}
+{% for entity in halEntities %}
+{{ macros::doc(lines=entity.doc) }}pub mod {{entity.name | snake}} {
+{%- include \"hal_mod.rs.entity_types\" %}
+{%- include \"hal_mod.rs.entity_regs\" %}
+{%- if peripheralTokens %}
 
+    {{ macros::token(owner=\"entity\") }}
+{%- endif %}
 
-    pub fn set() -> Writer \\{
-        Writer \\{
+    /// Writer for the entity, the fields that were set are written when it is dropped
+    pub fn set({% if peripheralTokens %}_: &mut Token{% endif %}) -> Writer {
+        Writer {
             _apply_on_drop: true,
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-            {peripheral.name | snake}_{register.name | snake}_reg: {peripheral.name}::{register.name}::new(),\
-{{endfor}}\
-{{endfor}}
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+            {{peripheral.name | snake}}_{{register.name | snake}}_reg: {{peripheral.name}}::{{register.name}}::new(),
+{%- endfor %}
+{%- endfor %}
         }
     }
-    pub fn read() -> Reader \\{
-        Reader \\{
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-            {peripheral.name | snake}_{register.name | snake}_reg: None,\
-{{endfor}}\
-{{endfor}}
+    /// Reader for the entity, each register is read when the first of its fields is accessed
+    pub fn read({% if peripheralTokens %}_: &Token{% endif %}) -> Reader {
+        Reader {
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+            {{peripheral.name | snake}}_{{register.name | snake}}_reg: None,
+{%- endfor %}
+{%- endfor %}
         }
     }
 
-    pub struct Writer \\{
+    /// Field values of the entity, see `set`
+    pub struct Writer {
         _apply_on_drop: bool,
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-        {peripheral.name | snake}_{register.name | snake}_reg: {peripheral.name}::{register.name}::Writer,\
-{{endfor}}\
-{{endfor}}
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+        {{peripheral.name | snake}}_{{register.name | snake}}_reg: {{peripheral.name}}::{{register.name}}::Writer,
+{%- endfor %}
+{%- endfor %}
     }
 
-    pub struct Reader \\{
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-        {peripheral.name | snake}_{register.name | snake}_reg: Option<{peripheral.name}::{register.name}::Reader>,\
-{{endfor}}
-{{endfor}}
+    /// Field values of the entity, see `read`
+    pub struct Reader {
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+        {{peripheral.name | snake}}_{{register.name | snake}}_reg: Option<{{peripheral.name}}::{{register.name}}::Reader>,
+{%- endfor %}
+{%- endfor %}
     }
 
-    impl Reader \\{
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-        fn update_{peripheral.name | snake}_{register.name | snake}_reg(&mut self) \\{
-            self.{peripheral.name | snake}_{register.name | snake}_reg = Some({peripheral.name}::{register.name}::read());\
+    impl Reader {
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+{%- set peripheral_name = peripheral.name | snake %}
+{%- set register_name = register.name | snake %}
+{%- set reg = peripheral_name ~ \"_\" ~ register_name ~ \"_reg\" %}
+        fn update_{{reg}}(&mut self) {
+            self.{{reg}} = Some({{peripheral.name}}::{{register.name}}::read());
         }
-//ToDo: Move to call...
-{{ for field in register.readFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self) -> {field.fieldType} \\{
-            if self.{peripheral.name | snake}_{register.name | snake}_reg.is_none() \\{
-                self.update_{peripheral.name | snake}_{register.name | snake}_reg();
-            }
-            self.{peripheral.name | snake}_{register.name | snake}_reg.as_ref().unwrap().{field.svdName}()
-        }\
-        {{endif}}\
-{{endfor}}\
-{{ for field in register.readWriteFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self) -> {field.fieldType} \\{
-            if self.{peripheral.name | snake}_{register.name | snake}_reg.is_none() \\{
-                self.update_{peripheral.name | snake}_{register.name | snake}_reg();
+{%- for field in register.readFields | concat(with=register.readWriteFields) %}
+{%- if field.visible and field.readAction %}
+        {{ macros::doc(lines=field.doc, indent=\"        \") }}///
+        /// Reads the register on every call since reading it has side effects
+        pub fn pop_{{field.name | snake}}(&mut self) -> {{ macros::rustReadType(fieldType=field.fieldType) }} {
+            {{peripheral.name}}::{{register.name}}::read().pop_{{field.svdName}}()
+        }
+{%- elif field.visible and register.readAction %}
+        {{ macros::doc(lines=field.doc, indent=\"        \") }}///
+        /// Reads the register on every call, which triggers the read action of its other fields.
+        /// The caller has to make sure that the data these fields lose is not needed.
+        pub unsafe fn {{field.name | snake}}(&mut self) -> {{ macros::rustReadType(fieldType=field.fieldType) }} {
+            {{peripheral.name}}::{{register.name}}::read().{{field.svdName}}()
+        }
+{%- elif field.visible %}
+        {{ macros::doc(lines=field.doc, indent=\"        \") }}pub fn {{field.name | snake}}(&mut self) -> {{ macros::rustReadType(fieldType=field.fieldType) }} {
+            if self.{{reg}}.is_none() {
+                self.update_{{reg}}();
             }
-            self.{peripheral.name | snake}_{register.name | snake}_reg.as_ref().unwrap().{field.svdName}()
-        }\
-        {{endif}}\
-{{endfor}}\
-{{endfor}}
-{{endfor}}
+            self.{{reg}}.as_ref().unwrap().{{field.svdName}}()
+        }
+{%- endif %}
+{%- endfor %}
+{%- endfor %}
+{%- endfor %}
     }
 
-    impl Writer \\{
-        pub fn write(&self) \\{
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}
-            self.{peripheral.name | snake}_{register.name | snake}_reg.write();\
-{{endfor}}
-{{endfor}}
+    impl Writer {
+        /// Writes the fields that were set, registers without such fields are not accessed
+        pub fn write(&self) {
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+{%- set peripheral_name = peripheral.name | snake %}
+{%- set register_name = register.name | snake %}
+{%- set reg = peripheral_name ~ \"_\" ~ register_name ~ \"_reg\" %}
+            if self.{{reg}}.mask() != 0 {
+                self.{{reg}}.write();
+            }
+{%- endfor %}
+{%- endfor %}
         }
-{{ for peripheral in entity.peripherals }}\
-{{ for register in peripheral.registers }}\
-{{ for field in register.writeFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self, value: {field.fieldType}) -> &mut Self \\{
-            self.{peripheral.name | snake}_{register.name | snake}_reg.{field.svdName}(value);
+{%- for peripheral in entity.peripherals %}
+{%- for register in peripheral.registers %}
+{%- for field in register.writeFields | concat(with=register.readWriteFields) %}
+{%- if field.visible %}
+        {{ macros::doc(lines=field.doc, indent=\"        \") }}pub fn {{field.name | snake}}(&mut self, value: {{ macros::rustType(fieldType=field.fieldType) }}) -> &mut Self {
+            self.{{peripheral.name | snake}}_{{register.name | snake}}_reg.{{field.svdName}}(value);
             self
-        }\
-        {{endif}}\
-{{endfor}}\
-{{ for field in register.readWriteFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self, value: {field.fieldType}) -> &mut Self \\{
-            self.{peripheral.name | snake}_{register.name | snake}_reg.{field.svdName}(value);
+        }
+{%- if field.clear is number %}
+        /// Clears `{{field.name | snake}}` by writing {{field.clear}} into it
+        pub fn clear_{{field.name | snake}}(&mut self) -> &mut Self {
+            self.{{peripheral.name | snake}}_{{register.name | snake}}_reg.clear_{{field.svdName}}();
             self
-        }\
-        {{endif}}\
-{{endfor}}\
-{{endfor}}
-{{endfor}}
+        }
+{%- endif %}
+{%- endif %}
+{%- endfor %}
+{%- endfor %}
+{%- endfor %}
     }
 
-    impl Drop for Writer \\{
-        fn drop(&mut self) \\{
-            if !self._apply_on_drop \\{
+    impl Drop for Writer {
+        fn drop(&mut self) {
+            if !self._apply_on_drop {
                 return;
             }
             self.write();
         }
     }
 }
-{{endfor}}
-";
-
-pub static HAL_MOD_RS_CONFIG_TEMPLATE: &'static str = "hal_mod.rs.config";
-static HAL_MOD_RS_CONFIG_TEMPLATE_CONTENT: &'static str = "\
-{{ for entity in @root }}
-    pub fn {entity.name | snake}() -> {entity.name | pascal}::{entity.name | pascal} \\{
-        {entity.name | pascal}::{entity.name | pascal}::new()
-    }
-    pub fn read_{entity.name | snake}() -> {entity.name | pascal}::{entity.name | pascal} \\{
-        {entity.name | pascal}::{entity.name | pascal}::read()
-    }\
-{{ endfor }}";
-
-pub static HAL_MOD_RS_ENTITY_STRUCT_TEMPLATE: &'static str = "hal_mod.rs.entity_struct";
-static HAL_MOD_RS_ENTITY_STRUCT_TEMPLATE_CONTENT: &'static str =
-    "    pub struct {name | pascal} \\{
-        _apply_on_drop: bool,\
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}\
-{{ for field in register.readFields }}\
-        {{if field.visible}}
-        pub _{field.name | snake}: Option<{field.fieldType}>,\
-        {{endif}}\
-{{ endfor }}\
-{{ for field in register.readWriteFields }}\
-        {{if field.visible}}
-        pub _{field.name | snake}: Option<{field.fieldType}>,\
-        {{endif}}\
-{{ endfor }}\
-{{ endfor }}\
-{{ endfor }}
-    }
-";
+{% endfor %}";
 
-pub static HAL_MOD_RS_ENTITY_REG_TEMPLATE: &'static str = "hal_mod.rs.entity_regs";
-static HAL_MOD_RS_ENTITY_REG_TEMPLATE_CONTENT: &'static str = "\
-{{ for peripheral in peripherals }}
+pub static HAL_MOD_RS_ENTITY_REG_TEMPLATE: &str = "hal_mod.rs.entity_regs";
+static HAL_MOD_RS_ENTITY_REG_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros -%}
+{%- for peripheral in entity.peripherals %}
     #[allow(non_snake_case)]
-    mod {peripheral.name}\\{\
-{{ for register in peripheral.registers }}
-        create_reg! \\{{peripheral.name}::{register.name}(u32) =>\
-            {{if register.readWriteFields}}
-            RW\\{
-                {{ for field in register.readWriteFields }}\
-                {field.svdName}({field.mask}{field.fieldTypeDelimiter}{field.fieldType}),\
-                {{ endfor }}
-            }\
-            {{endif}}\
-            {{if register.readFields}}
-            R\\{
-                {{ for field in register.readFields }}\
-                {field.svdName}({field.mask}{field.fieldTypeDelimiter}{field.fieldType}),\
-                {{ endfor }}
-            }\
-            {{endif}}\
-            {{if register.writeFields}}
-            W\\{
-                {{ for field in register.writeFields }}\
-                {field.svdName}({field.mask}{field.fieldTypeDelimiter}{field.fieldType}),\
-                {{ endfor }}
-            }\
-            {{endif}}
-        }\
-{{ endfor }}
-    }\
-{{ endfor }}";
-
-pub static HAL_MOD_RS_ENTITY_IMPL_TEMPLATE: &'static str = "hal_mod.rs.entity_impl";
-static HAL_MOD_RS_ENTITY_IMPL_TEMPLATE_CONTENT: &'static str =
-"    impl {name | pascal} \\{
-        pub fn new() -> {name | pascal} \\{
-            {name | pascal} \\{
-                _apply_on_drop: true,\
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}\
-{{ for field in register.readFields }}\
-                {{if field.visible}}
-                _{field.name | snake}: None,\
-                {{endif}}\
-{{ endfor }}\
-{{ for field in register.readWriteFields }}\
-                {{if field.visible}}
-                _{field.name | snake}: None,\
-                {{endif}}\
-{{ endfor }}\
-{{ endfor }}\
-{{ endfor }}
-            }
-        }
-        pub fn read() -> {name | pascal} \\{
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}
-            let {peripheral.name | snake}_{register.name | snake}_config = {peripheral.name}::{register.name}::read();\
-{{ endfor }}\
-{{ endfor }}
-            {name | pascal} \\{
-                _apply_on_drop: false,\
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}\
-{{ for field in register.readFields }}\
-                {{if field.visible}}
-                _{field.name | snake}: {peripheral.name | snake}_{register.name | snake}_config.{field.svdName}.ok(),\
-                {{endif}}\
-{{ endfor }}\
-{{ for field in register.readWriteFields }}\
-                {{if field.visible}}
-                _{field.name | snake}: {peripheral.name | snake}_{register.name | snake}_config.{field.svdName}.ok(),\
-                {{endif}}\
-{{ endfor }}\
-{{ endfor }}\
-{{ endfor }}
-            }
-        }
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}\
-{{ for field in register.writeFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self, {field.name | snake}: {field.fieldType}) -> &mut Self \\{
-            self._{field.name | snake} = Some({field.name | snake});
-            self
-        }\
-        {{endif}}\
-{{ endfor }}\
-{{ for field in register.readWriteFields }}\
-        {{if field.visible}}
-        pub fn {field.name | snake}(&mut self, {field.name | snake}: {field.fieldType}) -> &mut Self \\{
-            self._{field.name | snake} = Some({field.name | snake});
-            self
-        }\
-        {{endif}}\
-{{ endfor }}\
-{{ endfor }}\
-{{ endfor }}
-    }";
-
-pub static HAL_MOD_RS_ENTITY_TYPES_TEMPLATE: &'static str = "hal_mod.rs.entity_types";
-static HAL_MOD_RS_ENTITY_TYPES_TEMPLATE_CONTENT: &'static str = "\
-{{ for type in aggregatedTypes }}
-    #[allow(non_camel_case_types)]
-    #[derive(Copy, Clone, PartialEq, Eq)]
-    pub enum {type.name} \\{\
-        {{for value in type.values}}\
-        {{ if value }}
-        {value} = { @index },\
-        {{ endif }}\
-        {{ endfor }}
+    mod {{peripheral.name}} {
+        pub type RegisterBlock = crate::peripherals::{{peripheral.name}}::RegisterBlock;
+        pub use crate::peripherals::{{peripheral.name}}::ptr;
+{%- for register in peripheral.registers %}
+        {{ macros::createReg(peripheralName=peripheral.name, register=register, nameKey=\"svdName\") }}
+{%- endfor %}
     }
-    impl {type.name} \\{
-        pub fn from(x: u32) -> {type.name} \\{
-            match x \\{\
-                {{for value in type.values}}\
-                {{ if value }}
-                { @index } => {type.name}::{value},\
-                {{ endif }}\
-                {{ endfor }}
-                _ => panic!(\"Error while reading type {type.name}. Got value \\{}\",x),
-            }
-        }
-    }\
-{{ endfor }}
+{%- endfor %}";
+
+pub static HAL_MOD_RS_ENTITY_TYPES_TEMPLATE: &str = "hal_mod.rs.entity_types";
+static HAL_MOD_RS_ENTITY_TYPES_TEMPLATE_CONTENT: &str = "
+{%- for type in entity.aggregatedTypes %}
+    pub use crate::peripherals::{{type}};
+{%- endfor %}
 ";
 
-pub static HAL_MOD_RS_ENTITY_DROP_TEMPLATE: &'static str = "hal_mod.rs.entity_drop";
-static HAL_MOD_RS_ENTITY_DROP_TEMPLATE_CONTENT: &'static str = "    impl Drop for {name} \\{
-        fn drop(&mut self) \\{
-            if !self._apply_on_drop \\{
-                return;
-            }\
-{{if drop}}
-            {drop | unescaped}
-{{else}}\
-{{ for peripheral in peripherals }}\
-{{ for register in peripheral.registers }}
-            {peripheral.name}::{register.name}::new()
-            {{ for field in register.readWriteFields }}\
-            .{field.svdName}(&self._{field.name | snake})
-            {{ endfor }}\
-            {{ for field in register.writeFields }}\
-            .{field.svdName}(&self._{field.name | snake})
-            {{ endfor }}.write();\
-{{ endfor }}\
-{{ endfor }}\
-{{endif}}
+pub static ENUMERATED_TYPES_TEMPLATE: &str = "enumerated_types.rs";
+static ENUMERATED_TYPES_TEMPLATE_CONTENT: &str = "
+{%- import \"reg_def_macros.rs\" as macros %}
+{%- for type in enumeratedTypes %}
+{{ macros::doc(lines=type.doc) }}#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = \"defmt\", derive(defmt::Format))]
+pub enum {{type.name}} {
+    {%- for value in type.values %}
+    {{ macros::doc(lines=value.doc, indent=\"    \") }}{{value.name}} = {{value.value}},
+    {%- endfor %}
+}
+impl core::convert::TryFrom<u32> for {{type.name}} {
+    /// The raw value that has no variant
+    type Error = u32;
+    fn try_from(value: u32) -> Result<Self, u32> {
+        match value {
+            {%- for value in type.values %}
+            {{value.value}} => Ok({{type.name}}::{{value.name}}),
+            {%- endfor %}
+            _ => Err(value),
         }
-    }\
+    }
+}
+{%- endfor %}
 ";
 
 pub fn render_template_into_path<C>(template_id: &str, content: &C, path: &String)
@@ -520,8 +1239,14 @@ pub fn render_template_into_file<C>(template_id: &str, content: &C, file: &mut F
 where
     C: Serialize,
 {
-    let result: String = TEMPLATES
-        .render(template_id, &Context::from_serialize(&content).unwrap())
-        .unwrap();
-    file.write(result.as_bytes()).unwrap();
+    file.write_all(render_template(template_id, content).as_bytes()).unwrap();
+}
+
+pub fn render_template<C>(template_id: &str, content: &C) -> String
+where
+    C: Serialize,
+{
+    TEMPLATES
+        .render(template_id, &Context::from_serialize(content).unwrap())
+        .unwrap()
 }
@@ -0,0 +1,63 @@
+use serde_json::Value;
+
+/// Lists of a register that hold its fields, by the access of the fields
+pub static FIELD_KINDS: [&str; 3] = ["readWriteFields", "readFields", "writeFields"];
+
+/// The peripherals of the device: `content` of plain peripherals, `derived` of derived ones.
+/// Derived peripherals carry their merged registers after derived::annotate.
+pub fn peripherals(device: &Value) -> impl Iterator<Item = &Value> {
+    device["peripherals"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p.as_object())
+        .flat_map(|p| p.values())
+        .filter(|peripheral| peripheral.is_object())
+}
+
+/// Mutable version of `peripherals`
+pub fn peripherals_mut(device: &mut Value) -> impl Iterator<Item = &mut Value> {
+    device
+        .get_mut("peripherals")
+        .and_then(|peripherals| peripherals.as_array_mut())
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p.as_object_mut())
+        .flat_map(|p| p.values_mut())
+        .filter(|peripheral| peripheral.is_object())
+}
+
+/// The registers of a peripheral
+pub fn registers(peripheral: &Value) -> impl Iterator<Item = &Value> {
+    peripheral["registers"].as_array().into_iter().flatten()
+}
+
+/// Mutable version of `registers`
+pub fn registers_mut(peripheral: &mut Value) -> impl Iterator<Item = &mut Value> {
+    peripheral
+        .get_mut("registers")
+        .and_then(|registers| registers.as_array_mut())
+        .into_iter()
+        .flatten()
+}
+
+/// The fields of a register, in the order of FIELD_KINDS
+pub fn fields(register: &Value) -> impl Iterator<Item = &Value> {
+    FIELD_KINDS
+        .iter()
+        .flat_map(move |kind| register[*kind].as_array().into_iter().flatten())
+}
+
+/// Mutable version of `fields`, restricted to the lists named in `kinds`
+pub fn fields_mut<'a>(register: &'a mut Value, kinds: &[&str]) -> impl Iterator<Item = &'a mut Value> {
+    let mut lists: Vec<(usize, &mut Value)> = register
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|register| register.iter_mut())
+        .filter_map(|(key, fields)| Some((kinds.iter().position(|kind| kind == key)?, fields)))
+        .collect();
+    lists.sort_by_key(|(position, _)| *position);
+    lists
+        .into_iter()
+        .flat_map(|(_, fields)| fields.as_array_mut().into_iter().flatten())
+}
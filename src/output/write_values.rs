@@ -0,0 +1,40 @@
+use super::walk::{self, FIELD_KINDS};
+
+use serde_json::{json, Value};
+
+/// Evaluates `modifiedWriteValues` of the fields. Every register gets the masks of the bits
+/// that have to be written as zeros (`writeZerosMask`) or ones (`writeOnesMask`) when they are
+/// not touched by a read-modify-write, so that e.g. pending write-1-to-clear flags survive.
+/// Fields that are cleared by writing get `clear` with the value that clears them.
+pub fn annotate(device: &mut Value) {
+    for peripheral in walk::peripherals_mut(device) {
+        for register in walk::registers_mut(peripheral) {
+            let register_default = register["modifiedWriteValues"].clone();
+            let mut zeros = 0;
+            let mut ones = 0;
+            for field in walk::fields_mut(register, &FIELD_KINDS) {
+                let modified_write_values = match field["modifiedWriteValues"].as_str() {
+                    Some(value) => value.to_string(),
+                    None => register_default.as_str().unwrap_or_default().to_string(),
+                };
+                let mask = field["mask"].as_u64().unwrap_or(0);
+                // Writing back the value read must not change these fields
+                match modified_write_values.to_lowercase().as_str() {
+                    "onetoclear" => {
+                        zeros |= mask;
+                        field["clear"] = json!(1);
+                    }
+                    "zerotoclear" => {
+                        ones |= mask;
+                        field["clear"] = json!(0);
+                    }
+                    "onetotoggle" => zeros |= mask,
+                    "zerototoggle" | "zerotoset" => ones |= mask,
+                    _ => {}
+                }
+            }
+            register["writeZerosMask"] = json!(zeros);
+            register["writeOnesMask"] = json!(ones);
+        }
+    }
+}
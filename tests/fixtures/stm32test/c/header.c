@@ -0,0 +1,17 @@
+/* Checks the generated C header, compiled against include/ of the generated crate */
+#include <stddef.h>
+#include "stm32test.h"
+
+_Static_assert(USART1_BASE == 0x40013800UL, "base address of USART1");
+_Static_assert(USART2_BASE == 0x40004400UL, "base address of the derived USART2");
+_Static_assert(USART1_ISR_ORE_Pos == 3U, "position of USART1 ISR ORE");
+_Static_assert(USART1_ISR_ORE_Msk == 0x8UL, "mask of USART1 ISR ORE");
+_Static_assert(RCC_CR_MSIRANGE_Msk == (0xFUL << RCC_CR_MSIRANGE_Pos), "mask of RCC CR MSIRANGE");
+_Static_assert(offsetof(USART1_TypeDef, BRR) == 0xC, "offset of USART1 BRR");
+_Static_assert(offsetof(GPIOA_TypeDef, BSRR) == 0x18, "offset of GPIOA BSRR");
+_Static_assert(USART3_IRQn == 39, "interrupt number of USART3");
+
+void set_baud_rate(void) {
+    USART2->BRR = 0x1A1;
+    GPIOA->BSRR = GPIOA_BSRR_BS_Msk & (1UL << 2);
+}
@@ -0,0 +1,29 @@
+//! Driver test of derived peripherals, runs against the generated crate with the host-sim feature
+use rawhal_stm32test::peripherals::{USART1, USART2, USART3};
+use rawhal_stm32test::sim;
+
+#[test]
+fn derived_peripheral_accesses_its_own_registers() {
+    sim::reset();
+    let mut brr = USART2::BRR::new();
+    brr.BRR(0x1A1);
+    brr.write();
+    assert_eq!(USART2::BRR::ADDRESS, 0x4000_440C);
+    assert_eq!(sim::peek(USART2::BRR::ADDRESS), 0x1A1);
+    assert_eq!(sim::peek(USART1::BRR::ADDRESS), 0);
+    assert_eq!(USART2::BRR::read().BRR(), 0x1A1);
+    assert_eq!(USART1::BRR::read().BRR(), 0);
+}
+
+#[test]
+fn derived_peripheral_overrides_registers() {
+    sim::reset();
+    // USART3 widens BRR to 20 bits, the other registers are the ones of USART1
+    USART3::BRR::modify(|_, w| w.BRR(0xF_FFFF));
+    let brr: u32 = USART3::BRR::read().BRR();
+    assert_eq!(brr, 0xF_FFFF);
+    let brr: u16 = USART1::BRR::read().BRR();
+    assert_eq!(brr, 0);
+    assert_eq!(sim::peek(USART3::BRR::ADDRESS), 0xF_FFFF);
+    assert!(USART3::ISR::read().TXE());
+}
@@ -0,0 +1,19 @@
+//! Driver test of the Debug output of the readers, run against the generated crate with the host-sim feature
+use rawhal_stm32test::peripherals::RCC;
+use rawhal_stm32test::sim;
+
+#[test]
+fn readers_list_their_decoded_fields() {
+    sim::reset();
+    assert_eq!(
+        format!("{:?}", RCC::CR::read()),
+        "CR { bits: 0x63, HSIRDY: false, HSION: false, MSIRANGE: Range4M }"
+    );
+
+    // MSIRANGE 5 has no variant, so its raw value is shown
+    sim::poke(RCC::CR::ADDRESS, 0x553);
+    assert_eq!(
+        format!("{:?}", RCC::CR::read()),
+        "CR { bits: 0x553, HSIRDY: true, HSION: true, MSIRANGE: 0x5 }"
+    );
+}
@@ -0,0 +1,145 @@
+//! Driver tests of the simulated registers, run against the generated crate with the host-sim feature
+use rawhal_stm32test::peripherals::{GPIOA, RCC, USART1, USART2, USART3};
+use rawhal_stm32test::{console, sim};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Counts the writes of the register at `address`, which keeps the written value
+fn count_writes(address: usize) -> Rc<Cell<u32>> {
+    let writes = Rc::new(Cell::new(0));
+    let counter = writes.clone();
+    sim::on_write(address, move |_, value| {
+        counter.set(counter.get() + 1);
+        value
+    });
+    writes
+}
+
+#[test]
+fn registers_start_with_their_reset_value() {
+    sim::reset();
+    assert_eq!(sim::reset_value(USART1::ISR::ADDRESS), 0xC0);
+    assert_eq!(sim::peek(USART1::ISR::ADDRESS), 0xC0);
+    assert_eq!(sim::peek(RCC::CR::ADDRESS), 0x63);
+    assert!(USART1::ISR::read().TXE());
+    assert!(!USART1::ISR::read().UE());
+
+    USART1::ISR::modify(|_, w| w.UE(true));
+    assert_eq!(sim::peek(USART1::ISR::ADDRESS), 0xC1);
+    sim::reset();
+    assert_eq!(sim::peek(USART1::ISR::ADDRESS), 0xC0);
+}
+
+#[test]
+fn hooks_simulate_the_hardware() {
+    sim::reset();
+    // The ready flag follows the enable bit
+    sim::on_write(RCC::CR::ADDRESS, |_, value| {
+        let ready = (value & u64::from(RCC::CR::HSION::MASK)) << 2;
+        (value & !u64::from(RCC::CR::HSIRDY::MASK)) | ready
+    });
+    assert!(!RCC::CR::read().HSIRDY());
+    RCC::CR::modify(|_, w| w.HSION(true));
+    assert!(RCC::CR::read().HSIRDY());
+    RCC::CR::modify(|_, w| w.HSION(false));
+    assert!(!RCC::CR::read().HSIRDY());
+
+    // Every read pops the receive FIFO
+    let mut fifo = vec![0x42, 0x41];
+    sim::on_read(USART1::RDR::ADDRESS, move |_| fifo.pop().unwrap_or(0));
+    assert_eq!(USART1::RDR::read().pop_RDR(), 0x41);
+    assert_eq!(USART1::RDR::read().pop_RDR(), 0x42);
+    assert_eq!(USART1::RDR::read().pop_RDR(), 0);
+}
+
+#[test]
+fn modify_keeps_write_one_to_clear_flags() {
+    sim::reset();
+    // ORE and FE are cleared by writing 1, TXE is read-only
+    let flags = u64::from(USART1::ISR::ORE::MASK | USART1::ISR::FE::MASK);
+    let read_only = u64::from(USART1::ISR::TXE::MASK);
+    let writes = Rc::new(Cell::new(Vec::new()));
+    let written = writes.clone();
+    sim::on_write(USART1::ISR::ADDRESS, move |current, value| {
+        let mut values = written.take();
+        values.push(value);
+        written.set(values);
+        (value & !(flags | read_only)) | (current & read_only) | (current & flags & !value)
+    });
+    sim::poke(USART1::ISR::ADDRESS, 0xC0 | flags);
+
+    // The flags are written with 0, so setting UE does not clear them
+    USART1::ISR::modify(|_, w| w.UE(true));
+    let isr = USART1::ISR::read();
+    assert!(isr.UE() && isr.ORE() && isr.FE() && isr.TXE());
+
+    // Only the flag that is cleared explicitly is written with 1
+    USART1::ISR::modify(|_, w| w.clear_ORE());
+    let isr = USART1::ISR::read();
+    assert!(isr.UE() && !isr.ORE() && isr.FE());
+    assert_eq!(writes.take(), vec![0xC1, 0xC9]);
+}
+
+#[test]
+fn atomic_policies_change_only_the_written_fields() {
+    sim::reset();
+    // BSRR sets the pins of its lower half and clears the pins of its upper half in ODR
+    let odr = GPIOA::ODR::ADDRESS;
+    sim::on_write(GPIOA::BSRR::ADDRESS, move |_, value| {
+        let current = sim::peek(odr);
+        sim::poke(odr, (current & !(value >> 16)) | (value & 0xFFFF));
+        0
+    });
+    let odr_writes = count_writes(odr);
+    sim::poke(odr, 0b0101);
+    GPIOA::ODR::modify(|_, w| {
+        w.OD1(true);
+        w.OD2(false);
+    });
+    assert_eq!(sim::peek(odr), 0b0011);
+    assert_eq!(odr_writes.get(), 0);
+
+    // The bit-band writes of BRR end up in the register
+    USART1::BRR::modify(|_, w| w.BRR(0x1A1));
+    assert_eq!(sim::peek(USART1::BRR::ADDRESS), 0x1A1);
+    assert_eq!(USART1::BRR::read().BRR(), 0x1A1);
+    // USART3 overrides BRR and inherits the policy of USART1
+    let brr_writes = count_writes(USART3::BRR::ADDRESS);
+    USART3::BRR::modify(|_, w| w.BRR(0x1_01A1));
+    assert_eq!(USART3::BRR::read().BRR(), 0x1_01A1);
+    assert_eq!(brr_writes.get(), 20);
+
+    RCC::CFGR::modify(|_, w| w.HPRE(0b1000));
+    assert_eq!(RCC::CFGR::read().HPRE(), 0b1000);
+}
+
+#[test]
+fn bit_band_writes_change_only_their_register() {
+    sim::reset();
+    // GT shares the word of PSC, its alias addresses are those of bits 8 to 15 of the word
+    sim::poke(USART1::PSC::ADDRESS, 0x5A);
+    USART1::GT::modify(|_, w| w.GT(0xA5));
+    assert_eq!(sim::peek(USART1::GT::ADDRESS), 0xA5);
+    assert_eq!(sim::peek(USART1::PSC::ADDRESS), 0x5A);
+    assert_eq!(USART1::GT::read().GT(), 0xA5);
+
+    // Writes keep only the bits of the register, also those returned by hooks
+    sim::on_write(USART1::PSC::ADDRESS, |_, value| value | 0x300);
+    USART1::PSC::modify(|_, w| w.PSC(0x12));
+    assert_eq!(sim::peek(USART1::PSC::ADDRESS), 0x12);
+}
+
+#[test]
+fn entity_writes_only_the_registers_it_changed() {
+    sim::reset();
+    let isr_writes = count_writes(USART2::ISR::ADDRESS);
+    let rdr_writes = count_writes(USART2::RDR::ADDRESS);
+    console::set().baud_rate(0x1A1);
+    assert_eq!((isr_writes.get(), rdr_writes.get()), (0, 0));
+    assert_eq!(sim::peek(USART2::BRR::ADDRESS), 0x1A1);
+    assert_eq!(console::read().baud_rate(), 0x1A1);
+
+    // A register without written fields is not written, e.g. the read-only RDR
+    USART2::RDR::new().write();
+    assert_eq!(rdr_writes.get(), 0);
+}
@@ -0,0 +1,76 @@
+//! Driver test of the tracer, runs against the generated crate with the host-sim and trace features.
+//! The tracer is global, so all accesses are checked in a single test.
+#![cfg(feature = "trace")]
+use rawhal_stm32test::peripherals::{GPIOA, USART1};
+use rawhal_stm32test::sim;
+use rawhal_stm32test::trace::{self, Access, Kind};
+use std::sync::Mutex;
+
+static ACCESSES: Mutex<Vec<Access>> = Mutex::new(Vec::new());
+
+fn record(access: &Access) {
+    ACCESSES.lock().unwrap().push(*access);
+}
+
+/// Takes the accesses reported so far as (kind, peripheral, register, address, value, mask)
+fn take_accesses() -> Vec<(Kind, &'static str, &'static str, usize, u64, u64)> {
+    ACCESSES
+        .lock()
+        .unwrap()
+        .drain(..)
+        .map(|a| (a.kind, a.peripheral, a.register, a.address, a.value, a.mask))
+        .collect()
+}
+
+#[test]
+fn tracer_reports_every_access() {
+    sim::reset();
+    trace::set_tracer(record);
+
+    USART1::ISR::modify(|_, w| w.UE(true));
+    assert_eq!(
+        take_accesses(),
+        [
+            (Kind::Read, "USART1", "ISR", 0x4001_3800, 0xC0, 0xFFFF_FFFF),
+            (Kind::Write, "USART1", "ISR", 0x4001_3800, 0xC1, 0x1),
+        ]
+    );
+
+    // Set/clear registers are reported as themselves
+    GPIOA::ODR::modify(|_, w| {
+        w.OD1(true);
+        w.OD2(false);
+    });
+    assert_eq!(
+        take_accesses(),
+        [
+            (Kind::Read, "GPIOA", "ODR", 0x4800_0014, 0x0, 0xFFFF_FFFF),
+            (
+                Kind::Write,
+                "GPIOA",
+                "BSRR",
+                0x4800_0018,
+                0x4_0002,
+                0x4_0002
+            ),
+        ]
+    );
+
+    // Bit-band writes are reported at their alias address with the register and bit they change
+    USART1::BRR::modify(|_, w| w.BRR(0x5));
+    let accesses = take_accesses();
+    assert_eq!(accesses.len(), 1 + 16);
+    assert_eq!(
+        accesses[..4],
+        [
+            (Kind::Read, "USART1", "BRR", 0x4001_380C, 0x0, 0xFFFF_FFFF),
+            (Kind::Write, "USART1", "BRR", 0x4227_0180, 0x1, 0x1),
+            (Kind::Write, "USART1", "BRR", 0x4227_0184, 0x0, 0x2),
+            (Kind::Write, "USART1", "BRR", 0x4227_0188, 0x4, 0x4),
+        ]
+    );
+
+    trace::clear_tracer();
+    USART1::ISR::read();
+    assert!(take_accesses().is_empty());
+}
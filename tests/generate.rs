@@ -0,0 +1,253 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs svd2hal on tests/fixtures/<fixture> with `options` appended to its hal_config.yaml and
+/// returns the generated crate directory. `variant` names the output directory.
+fn generate(fixture: &str, variant: &str, options: &str) -> PathBuf {
+    let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture);
+    let output_dir = env::temp_dir().join(format!(
+        "svd2hal-{}-{}-{}",
+        fixture,
+        variant,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&output_dir);
+    fs::create_dir_all(&output_dir).unwrap();
+
+    // The svd patch paths are relative to the configuration, so the whole fixture is copied
+    let config_dir = output_dir.join("config");
+    fs::create_dir_all(&config_dir).unwrap();
+    for entry in fs::read_dir(&fixture_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() {
+            fs::copy(&path, config_dir.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    let config_path = config_dir.join("hal_config.yaml");
+    let config = fs::read_to_string(&config_path).unwrap() + options;
+    fs::write(&config_path, config).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_svd2hal"))
+        .arg(config_path)
+        .arg(format!("{}/", output_dir.display()))
+        .status()
+        .expect("Could not run svd2hal");
+    assert!(status.success(), "svd2hal failed for fixture {}", fixture);
+
+    output_dir.join(format!("rawhal-{}", fixture))
+}
+
+fn cargo_check(crate_dir: &Path, features: &[&str]) {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .arg("check")
+        .arg(format!("--features={}", features.join(",")))
+        .current_dir(crate_dir)
+        .env("CARGO_TARGET_DIR", crate_dir.join("target"))
+        .status()
+        .expect("Could not run cargo");
+    assert!(
+        status.success(),
+        "generated crate {} does not type-check with features {:?}",
+        crate_dir.display(),
+        features
+    );
+}
+
+/// Compiles the C files of tests/fixtures/<fixture>/c against the generated C header
+fn cc_check(crate_dir: &Path, fixture: &str) {
+    let c_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture)
+        .join("c");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    for entry in fs::read_dir(c_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let status = Command::new(&cc)
+            .args(["-std=c11", "-Wall", "-Wextra", "-Werror", "-fsyntax-only"])
+            .arg("-I")
+            .arg(crate_dir.join("include"))
+            .arg(&path)
+            .status()
+            .expect("Could not run the C compiler");
+        assert!(
+            status.success(),
+            "{} does not compile against the header of {}",
+            path.display(),
+            crate_dir.display()
+        );
+    }
+}
+
+/// Runs the driver tests of tests/fixtures/<fixture>/driver against the generated crate
+fn cargo_test(crate_dir: &Path, fixture: &str, features: &[&str]) {
+    let tests_dir = crate_dir.join("tests");
+    fs::create_dir_all(&tests_dir).unwrap();
+    let driver_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture)
+        .join("driver");
+    for entry in fs::read_dir(driver_dir).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, tests_dir.join(path.file_name().unwrap())).unwrap();
+    }
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .arg("test")
+        .arg(format!("--features={}", features.join(",")))
+        .current_dir(crate_dir)
+        .env("CARGO_TARGET_DIR", crate_dir.join("target"))
+        .status()
+        .expect("Could not run cargo");
+    assert!(
+        status.success(),
+        "driver tests of {} fail with features {:?}",
+        crate_dir.display(),
+        features
+    );
+}
+
+#[test]
+fn generated_crate_type_checks() {
+    let crate_dir = generate(
+        "stm32test",
+        "symbols",
+        "outputs: [reference, c_header, model]\n",
+    );
+    for file in &[
+        "Cargo.toml",
+        "build.rs",
+        "peripheral.x",
+        "device.x",
+        "src/lib.rs",
+        "src/macros.rs",
+        "src/peripherals.rs",
+        "src/hal.rs",
+        "src/interrupts.rs",
+        "src/access.rs",
+        "src/sim.rs",
+        "src/trace.rs",
+        "include/stm32test.h",
+        "doc/registers.md",
+        "model.json",
+    ] {
+        assert!(crate_dir.join(file).is_file(), "{} was not generated", file);
+    }
+    cargo_check(&crate_dir, &[]);
+    cargo_check(&crate_dir, &["rt"]);
+    cargo_check(&crate_dir, &["host-sim"]);
+    cargo_check(&crate_dir, &["trace"]);
+    cargo_check(&crate_dir, &["defmt"]);
+    cc_check(&crate_dir, "stm32test");
+
+    let reference = fs::read_to_string(crate_dir.join("doc/registers.md")).unwrap();
+    for line in &[
+        "| [USART2](#usart2) | `0x40004400` | Derived from USART1 |",
+        "| CR | `0x0` | 32 | read-write | `0x63` | Clock control register |",
+        "| BSRR | `0x18` | 32 | write-only | `0x0` | Bit set/reset register |",
+        "<tr><td colspan=\"21\" align=\"center\"><i>reserved</i></td><td colspan=\"1\" align=\"center\">HSIRDY</td>\
+         <td colspan=\"1\" align=\"center\"><i>reserved</i></td><td colspan=\"1\" align=\"center\">HSION</td>\
+         <td colspan=\"4\" align=\"center\">MSIRANGE</td><td colspan=\"4\" align=\"center\"><i>reserved</i></td></tr>",
+        "| 7:4 | MSIRANGE | read-write | `0x6` | MSI clock ranges<br>`0x0` Range100K: range 0 around 100 kHz<br>\
+         `0x1` Range200K: range 1 around 200 kHz<br>`0x6` Range4M: range 6 around 4 MHz (reset value) |",
+    ] {
+        assert!(
+            reference.lines().any(|l| l == *line),
+            "doc/registers.md lacks the line {}",
+            line
+        );
+    }
+
+    let model_json = fs::read_to_string(crate_dir.join("model.json")).unwrap();
+    let model: serde_json::Value = serde_json::from_str(&model_json).unwrap();
+    let peripheral = |name: &str| {
+        model["peripherals"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["name"] == name)
+            .unwrap_or_else(|| panic!("model.json lacks the peripheral {}", name))
+            .clone()
+    };
+    let usart2 = peripheral("USART2");
+    assert_eq!(usart2["derivedFrom"], "USART1");
+    assert_eq!(usart2["baseAddress"], 0x4000_4400);
+    let brr = &usart2["registers"][3];
+    assert_eq!(brr["name"], "BRR");
+    assert_eq!(brr["address"], 0x4000_440C);
+    assert_eq!(brr["fields"][0]["mask"], 0xFFFF);
+    let isr_fields = &peripheral("USART1")["registers"][0]["fields"];
+    assert_eq!(isr_fields[0]["name"], "ORE");
+    assert_eq!(isr_fields[0]["mask"], 0x8);
+    assert_eq!(isr_fields[0]["modifiedWriteValues"], "oneToClear");
+    let msirange = &peripheral("RCC")["registers"][0]["fields"][1];
+    assert_eq!(msirange["mask"], 0xF0);
+    assert_eq!(msirange["enumeratedType"], "MSIRANGE_A");
+    let ndtr = &peripheral("DMA1")["registers"][2];
+    assert_eq!(ndtr["name"], "CH_NDTR");
+    assert_eq!(ndtr["addressOffset"], 0xC);
+    assert_eq!(ndtr["address"], 0x4002_000C);
+    let ccr = &peripheral("DMA2")["registers"][1];
+    assert_eq!(ccr["elements"][1]["name"], "CCR2");
+    assert_eq!(ccr["elements"][1]["address"], 0x4002_041C);
+    assert!(model["enumeratedTypes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|t| t["name"] == "MSIRANGE_A" && t["values"][2]["value"] == 6));
+    let console = &model["entities"][1];
+    assert_eq!(console["name"], "Console");
+    assert!(console["fields"].as_array().unwrap().iter().any(|f| {
+        f["name"] == "baud_rate"
+            && f["peripheral"] == "USART2"
+            && f["address"] == 0x4000_440C
+            && f["mask"] == 0xFFFF
+    }));
+    // Only the resolved data, no template data
+    for key in &[
+        "doc",
+        "brief",
+        "bitFields",
+        "registerBlock",
+        "criticalSection",
+    ] {
+        assert!(
+            !model_json.contains(&format!("\"{}\"", key)),
+            "model.json contains the template data {}",
+            key
+        );
+    }
+
+    cargo_test(&crate_dir, "stm32test", &["host-sim"]);
+    cargo_test(&crate_dir, "stm32test", &["host-sim", "trace"]);
+}
+
+#[test]
+fn generated_crate_with_pointer_access_type_checks() {
+    let crate_dir = generate("stm32test", "pointers", "peripheral_access: pointers\n");
+    assert!(
+        !crate_dir.join("peripheral.x").exists(),
+        "peripheral.x is not needed with pointer access"
+    );
+    for file in &["include/stm32test.h", "doc/registers.md", "model.json"] {
+        assert!(
+            !crate_dir.join(file).exists(),
+            "{} is only generated if selected in outputs",
+            file
+        );
+    }
+    cargo_check(&crate_dir, &[]);
+    cargo_check(&crate_dir, &["rt"]);
+    cargo_check(&crate_dir, &["host-sim"]);
+}
+
+#[test]
+fn generated_crate_with_tokens_type_checks() {
+    let crate_dir = generate("stm32test", "tokens", "peripheral_tokens: true\n");
+    cargo_check(&crate_dir, &[]);
+    cargo_check(&crate_dir, &["host-sim"]);
+}